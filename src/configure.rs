@@ -1,52 +1,113 @@
 //! Code relating to configuring the radio.
 
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
 use crate::{
-    params::{LoraSpreadingFactor, ModulationParams8x, PacketParams},
-    shared::{OpCode, RadioError, Register::Reg8x, Register8x},
-    OperatingMode, PacketType, Radio, RadioConfig,
+    params::{
+        CadExitMode, CadParams, FlrcBt, FlrcCrc, FlrcSyncWordCombo, FlrcSyncWordLen,
+        GfskBitrateBandwidth8x, GfskModIndex8x, GfskPulseShape, InvertIq, LoraHeaderType,
+        LoraSpreadingFactor, ModulationParams8x, ModulationParamsGfsk6x, ModulationParamsGfsk8x,
+        PacketParams,
+    },
+    shared::{
+        ConfigError, OpCode, RadioError,
+        Register::{Reg6x, Reg8x},
+        Register6x, Register8x,
+    },
+    DioPull, OperatingMode, OutputPower6x, PacketType6x, PacketType8x, Radio, RadioConfig,
+    SleepConfig, FREQ_MAX_8X, FREQ_MIN_8X,
 };
 
 // The timing factor used to convert between 24-bit integer timing conversions used
 // by the radio, and ms. Eg: Sleep Duration = sleepPeriod * 15.625 µs. Same for rx mode duration.
 // DS, section 13.1.7 (6x)
 //
-// Note: On 8x, we can choose from four of these. We use the same one as 6x, always, for now.
+// Note: On 8x, we can choose from four of these; see `PERIOD_BASES_8X`.
 const TIMING_FACTOR_MS_6X: f32 = 0.015_625;
+const MAX_PERIOD_COUNT_6X: u32 = 0xff_fffe;
+
+// 8x timeout period bases: (register value, duration in ms). DS, Table 11-24.
+const PERIOD_BASES_8X: [(u8, f32); 4] = [(0x00, 0.015_625), (0x01, 0.0625), (0x02, 1.), (0x03, 4.)];
+const MAX_PERIOD_COUNT_8X: u16 = 0xfffe;
+
+// The FLRC CRC polynomial and seed registers are 16 bits each. DS, Table 13-1.
+const FLRC_CRC_REG_MAX: u32 = 0xffff;
+
+// The shortest 6x LoRa preamble we accept, in symbols; the longest is 65,535. DS, section 13.4.6.2.
+pub(crate) const LORA_PREAMBLE_MIN_6X: u16 = 10;
+
+/// Encode a LoRa preamble length, in symbols, into the 8x packet param: preamble length =
+/// LORA_PBLE_LEN_MANT * 2^(LORA_PBLE_LEN_EXP), with the exponent in the upper nibble, and the mantissa
+/// in the lower. DS, section 11.7.8. Returns `None` if the length can't be represented exactly.
+pub(crate) fn lora_preamble_8x(symbols: u16) -> Option<u8> {
+    if symbols == 0 {
+        return None;
+    }
+
+    let exp = symbols.trailing_zeros() as u8;
+    let mant = symbols >> exp;
+
+    if mant > 0xf {
+        return None;
+    }
 
-// Oscillator frequency in Mhz.
-const F_XTAL_6X: f32 = 32_000_000.;
-const F_XTAL_8X: f32 = 52_000_000.;
+    Some((exp << 4) | mant as u8)
+}
+
+/// The CalibrateImage frequency band parameters for the band containing `rf_freq`. DS, Table 9-2:
+/// 430 - 440MHz, 470 - 510MHz, 779 - 787MHz, 863 - 870MHz, and 902 - 928MHz. Frequencies between these
+/// use the band above; eg 450MHz uses 470 - 510MHz. Frequencies above 870MHz use 902 - 928MHz.
+fn image_cal_bands_6x(rf_freq: u32) -> (u8, u8) {
+    match rf_freq {
+        0..=440_000_000 => (0x6b, 0x6f),
+        440_000_001..=510_000_000 => (0x75, 0x81),
+        510_000_001..=787_000_000 => (0xc1, 0xc5),
+        787_000_001..=870_000_000 => (0xd7, 0xdb),
+        _ => (0xe1, 0xe9),
+    }
+}
+
+// Calibrate all blocks: RC64k, RC13M, PLL, ADC pulse, ADC bulk N and P, and image. DS, Table 13-18.
+const CALIBRATE_ALL_6X: u8 = 0x7f;
 
-// These constants are pre-computed
-const FREQ_CONST_6X: f32 = F_XTAL_6X / (1 << 25) as f32;
-const FREQ_CONST_8X: f32 = F_XTAL_8X / (1 << 18) as f32;
+// Oscillator frequency in Hz.
+const F_XTAL_6X: u64 = 32_000_000;
+const F_XTAL_8X: u64 = 52_000_000;
 
-impl Radio {
+// The frequency register is RF frequency * 2^n / F_XTAL. 6x: DS, section 13.4.1. 8x: section 11.7.3.
+const FREQ_SHIFT_6X: u32 = 25;
+const FREQ_SHIFT_8X: u32 = 18;
+
+// LR-FHSS on 6x uses a fixed 488.28125 b/s bit rate. br = 32 * F_XTAL / bit rate; DS, section 13.4.5.1.
+const LR_FHSS_BR_6X: u32 = (32. * F_XTAL_6X as f32 / 488.281_25) as u32;
+
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
     /// 6x: See DS, section 13.4.1 for this computation.
-    /// 8x: See DS, section 11.7.3.
+    /// 8x: See DS, section 11.7.3. `rf_freq` is both the Tx frequency, and the effective Rx frequency;
+    /// see `shared::IF_FREQ_8X`.
     pub(crate) fn set_rf_freq(&mut self) -> Result<(), RadioError> {
-        match &self.config {
-            RadioConfig::R6x(config) => {
-                // We convert to u64 to prevent an overflow.
-                let rf_freq_raw = ((config.rf_freq as f32 / FREQ_CONST_6X) as u32).to_be_bytes();
+        let rf_freq_raw = self.rf_freq_raw().to_be_bytes();
 
-                self.interface.write(&[
-                    OpCode::SetRfFrequency as u8,
-                    rf_freq_raw[0],
-                    rf_freq_raw[1],
-                    rf_freq_raw[2],
-                    rf_freq_raw[3],
-                ])
-            }
-            RadioConfig::R8x(config) => {
+        match &self.config {
+            RadioConfig::R6x(_) => self.interface.write(&[
+                OpCode::SetRfFrequency as u8,
+                rf_freq_raw[0],
+                rf_freq_raw[1],
+                rf_freq_raw[2],
+                rf_freq_raw[3],
+            ]),
+            RadioConfig::R8x(_) => {
                 // See section 4.3, and this from the table.
                 // "The LSB of rfFrequency is equal to the PLL step i.e. 52e6/2^18 Hz, where 52e6 is the crystal frequency in Hz. SetRfFrequency()
                 // defines the Tx frequency. The Rx frequency is down-converted to the IF. The IF is set by default to 1.3 MHz. This
                 // configuration is handled internally by the transceiver, there is no need for the user to take this offset into account when
                 // configuring SetRfFrequency. This must be called after SetPacket type."
-                let rf_freq_raw = ((config.rf_freq as f32 / FREQ_CONST_8X) as u32).to_be_bytes();
+
+                // No calibration is required here when changing frequency, including across the 2.4GHz band:
+                // Unlike 6x, 8x has no image calibration or calibrate commands; the PLL and other blocks are
+                // calibrated automatically when entering FS, Tx, or Rx. (DS, Table 11-5, and section 11.6)
                 self.interface.write(&[
-                    OpCode::SetRfFrequency.val_8x(),
+                    OpCode::SetRfFrequency.val_8x()?,
                     rf_freq_raw[1],
                     rf_freq_raw[2],
                     rf_freq_raw[3],
@@ -55,36 +116,112 @@ impl Radio {
         }
     }
 
+    /// 8x LoRa only. Nudge `rf_freq` toward the peer's frequency, using the frequency error of the last
+    /// received packet, and send it to the radio. Run this after a good packet (CRC OK), and before
+    /// starting the next operation; this improves reception at narrow bandwidths. Returns the correction
+    /// applied, in Hz.
+    ///
+    /// Each call applies half the measured error, and at most 1/8 of the bandwidth, so a single bad
+    /// reading can't pull the radio far off; repeated calls converge on the peer's frequency, halving the
+    /// remaining offset each packet. The result stays within the 8x band. Note that `send_payload`
+    /// and `receive` overwrite `rf_freq` with their argument; pass them the corrected `rf_freq` from the
+    /// config to keep the correction.
+    pub fn auto_correct_frequency(&mut self) -> Result<i32, RadioError> {
+        let fei = self.get_fei()?;
+
+        let RadioConfig::R8x(config) = &mut self.config else {
+            return Err(RadioError::Config);
+        };
+        let max_step = match &config.modulation_params {
+            ModulationParams8x::Lora(m) => (m.mod_bandwidth.hz() / 8) as i32,
+            _ => return Err(RadioError::ParamMismatch),
+        };
+
+        let correction = (fei / 2).clamp(-max_step, max_step);
+
+        let rf_freq = (config.rf_freq as i64 + correction as i64)
+            .clamp(FREQ_MIN_8X as i64, FREQ_MAX_8X as i64) as u32;
+        let applied = rf_freq as i64 - config.rf_freq as i64;
+        config.rf_freq = rf_freq;
+
+        self.set_rf_freq()?;
+
+        Ok(applied as i32)
+    }
+
+    /// The frequency register value written by `set_rf_freq`, for the configured `rf_freq`.
+    fn rf_freq_raw(&self) -> u32 {
+        match &self.config {
+            RadioConfig::R6x(config) => rf_freq_raw(config.rf_freq, false),
+            RadioConfig::R8x(config) => rf_freq_raw(config.rf_freq, true),
+        }
+    }
+
+    /// The frequency the radio actually tunes to, in Hz, for the configured `rf_freq`. This differs from
+    /// `rf_freq` by up to one PLL step (~1Hz on 6x, ~198Hz on 8x), due to truncation when computing
+    /// the register value. It's computed from that register value, rounded to the nearest Hz.
+    ///
+    /// Neither DS documents a register holding the frequency set by SetRfFrequency, so we can't read it
+    /// back from the radio. (The 6x `Freq0x` registers are for intra-packet hopping, not the RF frequency.)
+    /// To chase PLL lock issues, check for `RadioError::PllLock`, or (6x) the device errors.
+    pub fn actual_frequency(&self) -> u32 {
+        let (f_xtal, shift) = match self.config {
+            RadioConfig::R6x(_) => (F_XTAL_6X, FREQ_SHIFT_6X),
+            RadioConfig::R8x(_) => (F_XTAL_8X, FREQ_SHIFT_8X),
+        };
+
+        // We use u64 to prevent an overflow.
+        ((self.rf_freq_raw() as u64 * f_xtal + (1 << (shift - 1))) >> shift) as u32
+    }
+
     /// Send modulation parameters found in the config, to the radio.
     /// 6x DS, section 13.4.5. Parameters depend on the packet type.
     /// 8x DS: Section 11.7.7
     pub fn set_mod_params(&mut self) -> Result<(), RadioError> {
         match &self.config {
             RadioConfig::R6x(config) => {
-                let mut p1 = 0;
-                let mut p2 = 0;
-                let mut p3 = 0;
-                let mut p4 = 0;
-                let p5 = 0;
-                let p6 = 0;
-                let p7 = 0;
-                let p8 = 0;
+                let p1;
+                let p2;
+                let p3;
+                let p4;
+                let mut p5 = 0;
+                let mut p6 = 0;
+                let mut p7 = 0;
+                let mut p8 = 0;
 
                 match config.packet_type {
-                    PacketType::Gfsk => {
-                        unimplemented!()
+                    PacketType6x::Gfsk => {
+                        let m = &config.modulation_params_gfsk;
+                        let (br, fdev) = gfsk_br_fdev_6x(m)?;
+                        let br = br.to_be_bytes();
+                        let fdev = fdev.to_be_bytes();
+
+                        p1 = br[1];
+                        p2 = br[2];
+                        p3 = br[3];
+                        p4 = m.pulse_shape as u8;
+                        p5 = m.bandwidth as u8;
+                        p6 = fdev[1];
+                        p7 = fdev[2];
+                        p8 = fdev[3];
                     }
-                    PacketType::Lora => {
+                    PacketType6x::Lora => {
+                        config.modulation_params.validate(config.rf_freq)?;
+
                         p1 = config.modulation_params.spreading_factor as u8;
                         p2 = config.modulation_params.mod_bandwidth as u8;
                         p3 = config.modulation_params.coding_rate as u8;
                         p4 = config.modulation_params.low_data_rate_optimization as u8;
                     }
-                    PacketType::LrFhssFlrc => {
-                        // todo: FHSS on 8x
-                        unimplemented!()
+                    PacketType6x::LrFhss => {
+                        // LR-FHSS modulation is fixed: GFSK-style bit rate, with Gaussian BT 1 shaping.
+                        // Bandwidth and frequency deviation are unused, since LR-FHSS is Tx only.
+                        let br = LR_FHSS_BR_6X.to_be_bytes();
+                        p1 = br[1];
+                        p2 = br[2];
+                        p3 = br[3];
+                        p4 = GfskPulseShape::GaussianBt1 as u8;
                     }
-                    _ => unimplemented!(),
                 }
 
                 // todo: Confirm we can ignore unused params.
@@ -102,39 +239,41 @@ impl Radio {
                 ])?;
             }
             RadioConfig::R8x(config) => {
-                let mut p1 = 0;
-                let mut p2 = 0;
-                let mut p3 = 0;
+                let p1;
+                let p2;
+                let p3;
 
                 match config.packet_type {
-                    PacketType::Gfsk => {
-                        unimplemented!()
-                    }
-                    PacketType::Lora => match &config.modulation_params {
+                    // BLE uses GFSK modulation.
+                    PacketType8x::Gfsk | PacketType8x::Ble => match &config.modulation_params {
+                        ModulationParams8x::Gfsk(m) => {
+                            p1 = m.bitrate_bandwidth as u8;
+                            p2 = m.mod_index as u8;
+                            p3 = m.bt as u8;
+                        }
+                        _ => return Err(RadioError::ParamMismatch),
+                    },
+                    PacketType8x::Lora => match &config.modulation_params {
                         ModulationParams8x::Lora(m) => {
                             p1 = m.spreading_factor.val_8x();
                             p2 = m.mod_bandwidth as u8;
                             p3 = m.coding_rate as u8;
                         }
-                        ModulationParams8x::Flrc(_) => {
-                            panic!("Found FLRC modulation params for Lora packet type.")
-                        }
+                        _ => return Err(RadioError::ParamMismatch),
                     },
-                    PacketType::LrFhssFlrc => match &config.modulation_params {
-                        ModulationParams8x::Lora(_) => {
-                            panic!("Found Lora modulation params for FLRC packet type.")
-                        }
+                    PacketType8x::Flrc => match &config.modulation_params {
                         ModulationParams8x::Flrc(m) => {
                             p1 = m.bitrate as u8;
                             p2 = m.coding_rate as u8;
                             p3 = m.bt as u8;
                         }
+                        _ => return Err(RadioError::ParamMismatch),
                     },
-                    _ => unimplemented!(),
+                    _ => return Err(RadioError::Config),
                 }
 
                 self.interface
-                    .write(&[OpCode::SetModulationParams.val_8x(), p1, p2, p3])?;
+                    .write(&[OpCode::SetModulationParams.val_8x()?, p1, p2, p3])?;
 
                 // See the note below Table 14-47: This write must be performed after setting mod params
                 // on 8x.
@@ -146,8 +285,10 @@ impl Radio {
                     };
                     self.interface
                         .write_reg_word(Reg8x(Register8x::SfAdditionalConfiguration), sf_cfg_val)?;
-                    self.interface
-                        .write_reg_word(Reg8x(Register8x::FrequencyErrorCorrection), 0x1)?;
+                    self.interface.write_reg_word(
+                        Reg8x(Register8x::FrequencyErrorCorrection),
+                        config.freq_error_correction as u8,
+                    )?;
                 }
             }
         }
@@ -155,31 +296,80 @@ impl Radio {
         Ok(())
     }
 
-    /// Send packet parameters found in the config, to the radio.
-    /// 6x: DS, section 13.4.6.
-    /// 8x: DS, section 11.7.8
+    /// Send packet parameters found in the config, to the radio, using the Tx IQ polarity.
     pub(crate) fn set_packet_params(&mut self) -> Result<(), RadioError> {
-        let mut p1 = 0;
-        let mut p2 = 0;
-        let mut p3 = 0;
-        let mut p4 = 0;
-        let mut p5 = 0;
-        let mut p6 = 0;
-        let p7 = 0;
-        let p8 = 0;
-        let p9 = 0;
+        self.write_packet_params(false)
+    }
 
-        match &self.config {
+    /// Send packet parameters found in the config, to the radio, using the Rx IQ polarity.
+    pub(crate) fn set_packet_params_rx(&mut self) -> Result<(), RadioError> {
+        self.write_packet_params(true)
+    }
+
+    /// Set the LoRa preamble length, in symbols, and send it to the radio. Useful for duty-cycled receivers,
+    /// where the preamble must span the receiver's wake interval. 6x: 10 - 65,535 symbols. 8x: Must be
+    /// representable as `mantissa * 2^exponent`, with a mantissa of 1 - 15; eg 12, 24, or 240.
+    /// Returns `RadioError::Config` if the packet type isn't LoRa.
+    pub fn set_preamble_len(&mut self, symbols: u16) -> Result<(), RadioError> {
+        match &mut self.config {
             RadioConfig::R6x(config) => {
-                // The preamble is between 10 and 65,535 symbols.
-                if config.packet_params.preamble_len < 10 {
+                if config.packet_type != PacketType6x::Lora {
                     return Err(RadioError::Config);
                 }
+                if symbols < LORA_PREAMBLE_MIN_6X {
+                    return Err(RadioError::InvalidConfig(ConfigError::PreambleLen));
+                }
+                config.packet_params.preamble_len = symbols;
+            }
+            RadioConfig::R8x(config) => {
+                let PacketParams::Lora(p) = &mut config.packet_params else {
+                    return Err(RadioError::Config);
+                };
+                if lora_preamble_8x(symbols).is_none() {
+                    return Err(RadioError::InvalidConfig(ConfigError::PreambleLen));
+                }
+                p.preamble_len = symbols;
+            }
+        }
+
+        self.set_packet_params()
+    }
+
+    /// 6x: DS, section 13.4.6.
+    /// 8x: DS, section 11.7.8
+    fn write_packet_params(&mut self, rx: bool) -> Result<(), RadioError> {
+        let p1;
+        let p2;
+        let p3;
+        let p4;
+        let p5;
+        let p6;
+        let mut p7 = 0;
+        let mut p8 = 0;
+        let mut p9 = 0;
+
+        match &self.config {
+            RadioConfig::R6x(config) => {
                 match config.packet_type {
-                    PacketType::Gfsk => {
-                        unimplemented!()
+                    PacketType6x::Gfsk => {
+                        let p = &config.packet_params_gfsk;
+                        let preamble_len = p.preamble_len.to_be_bytes();
+
+                        p1 = preamble_len[0];
+                        p2 = preamble_len[1];
+                        p3 = p.preamble_detector as u8;
+                        p4 = p.sync_word_len;
+                        p5 = p.addr_comp as u8;
+                        p6 = p.packet_type as u8;
+                        p7 = p.payload_len;
+                        p8 = p.crc as u8;
+                        p9 = p.whitening as u8;
                     }
-                    PacketType::Lora => {
+                    PacketType6x::Lora => {
+                        if config.packet_params.preamble_len < LORA_PREAMBLE_MIN_6X {
+                            return Err(RadioError::InvalidConfig(ConfigError::PreambleLen));
+                        }
+
                         let preamble_len = config.packet_params.preamble_len.to_be_bytes();
 
                         p1 = preamble_len[0];
@@ -187,12 +377,13 @@ impl Radio {
                         p3 = config.packet_params.header_type.val_6x();
                         p4 = config.packet_params.payload_len;
                         p5 = config.packet_params.crc_enabled.val_6x();
-                        p6 = config.packet_params.invert_iq.val_6x();
+                        p6 = self.iq_for_dir(rx).val_6x();
                     }
-                    PacketType::LrFhssFlrc => {
-                        unimplemented!()
+                    PacketType6x::LrFhss => {
+                        // There are no packet params for LR-FHSS; the frame, including its header and
+                        // hopping sequence, is built by the host.
+                        return Ok(());
                     }
-                    _ => unimplemented!(),
                 }
 
                 // todo: Confirm we can ignore unused params.
@@ -208,7 +399,12 @@ impl Radio {
                     p7,
                     p8,
                     p9,
-                ])
+                ])?;
+
+                if config.packet_type == PacketType6x::Lora {
+                    self.iq_polarity_workaround(self.iq_for_dir(rx))?;
+                }
+                Ok(())
             }
             RadioConfig::R8x(config) => {
                 // Check preamble. len. Recommended: 12.
@@ -216,45 +412,42 @@ impl Radio {
                 //     return Err(RadioError::Config);
                 // }
 
-                let mut p1 = 0;
-                let mut p2 = 0;
-                let mut p3 = 0;
-                let mut p4 = 0;
+                let p1;
+                let p2;
+                let p3;
+                let p4;
                 let mut p5 = 0;
                 let mut p6 = 0;
-                let p7 = 0;
+                let mut p7 = 0;
 
                 match config.packet_type {
-                    PacketType::Gfsk => {
-                        unimplemented!()
-                    }
-                    PacketType::Lora => {
+                    PacketType8x::Gfsk => match &config.packet_params {
+                        PacketParams::Gfsk(p) => {
+                            p1 = p.preamble_len as u8;
+                            p2 = p.sync_word_len as u8;
+                            p3 = p.sync_word_combo as u8;
+                            p4 = p.packet_type as u8;
+                            p5 = p.payload_len;
+                            p6 = p.crc as u8;
+                            p7 = p.whitening as u8;
+                        }
+                        _ => return Err(RadioError::ParamMismatch),
+                    },
+                    PacketType8x::Lora => {
                         match &config.packet_params {
                             PacketParams::Lora(p) => {
                                 // Note: The preamble here is handled differently from SX126x, to fit in a single param.
-                                // preamble length = LORA_PBLE_LEN_MANT*2^(LORA_PBLE_LEN_EXP)
-                                let pble_len_maint = p.preamble_len as u8;
-                                // Hard-set this at 0 for now; use `maint` raw. Limited to up to 16 until this is changed.
-                                let pble_len_exp: u8 = 0;
-
-                                let preamble_len =
-                                    ((pble_len_exp & 0xf) << 4) | (pble_len_maint & 0xf);
-
-                                p1 = preamble_len;
+                                p1 = lora_preamble_8x(p.preamble_len)
+                                    .ok_or(RadioError::InvalidConfig(ConfigError::PreambleLen))?;
                                 p2 = p.header_type.val_8x();
                                 p3 = p.payload_len;
                                 p4 = p.crc_enabled.val_8x();
-                                p5 = p.invert_iq.val_8x();
-                            }
-                            PacketParams::Flrc(_) => {
-                                panic!("Found FLRC packet params for Lora packet type.")
+                                p5 = p.iq_for_dir(rx).val_8x();
                             }
+                            _ => return Err(RadioError::ParamMismatch),
                         }
                     }
-                    PacketType::LrFhssFlrc => match &config.packet_params {
-                        PacketParams::Lora(_) => {
-                            panic!("Found Lora packet params for FLRC packet type.")
-                        }
+                    PacketType8x::Flrc => match &config.packet_params {
                         PacketParams::Flrc(p) => {
                             p1 = p.preamble_len as u8;
                             p2 = p.sync_word_len as u8;
@@ -263,12 +456,22 @@ impl Radio {
                             p5 = p.payload_len;
                             p6 = p.crc as u8;
                         }
+                        _ => return Err(RadioError::ParamMismatch),
+                    },
+                    PacketType8x::Ble => match &config.packet_params {
+                        PacketParams::Ble(p) => {
+                            p1 = p.connection_state as u8;
+                            p2 = p.crc as u8;
+                            p3 = 0; // Test payload; unused outside of test mode.
+                            p4 = p.whitening as u8;
+                        }
+                        _ => return Err(RadioError::ParamMismatch),
                     },
-                    _ => unimplemented!(), // BLE and ranging.
+                    _ => return Err(RadioError::Config), // Ranging.
                 }
 
                 self.interface.write(&[
-                    OpCode::SetPacketParams.val_8x(),
+                    OpCode::SetPacketParams.val_8x()?,
                     p1,
                     p2,
                     p3,
@@ -281,17 +484,129 @@ impl Radio {
         }
     }
 
+    /// The LoRa IQ polarity to use for the given direction.
+    fn iq_for_dir(&self, rx: bool) -> InvertIq {
+        let p = match &self.config {
+            RadioConfig::R6x(c) => &c.packet_params,
+            RadioConfig::R8x(c) => match &c.packet_params {
+                PacketParams::Lora(p) => p,
+                _ => return InvertIq::Standard,
+            },
+        };
+
+        p.iq_for_dir(rx)
+    }
+
+    /// 8x only. Set LoRa explicit or implicit header mode directly, using the `LoraHeaderMode` register,
+    /// and the payload length used in implicit mode. Implicit-header reception requires both, since
+    /// the length isn't sent over the air. Run by `receive` when fixed-length packets are configured.
+    /// DS, Table 13-1.
+    pub fn set_lora_header_mode(
+        &mut self,
+        header_type: LoraHeaderType,
+        payload_len: u8,
+    ) -> Result<(), RadioError> {
+        if !self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        let val = self
+            .interface
+            .read_reg_word(Reg8x(Register8x::LoraHeaderMode))?;
+        self.interface.write_reg_word(
+            Reg8x(Register8x::LoraHeaderMode),
+            (val & 0x7f) | header_type.val_8x(),
+        )?;
+
+        self.interface
+            .write_reg_word(Reg8x(Register8x::PayloadLength), payload_len)
+    }
+
+    /// 8x only. Set the LoRa sync peak attenuation register; an advanced setting that may improve reception
+    /// in strong-interference conditions, eg crowded 2.4 GHz environments. DS, Table 13-1 lists this
+    /// register, but doesn't document its default or range. Read the reset value with
+    /// `interface.read_reg_word` before changing it, and tune from there.
+    pub fn set_synch_peak_attenuation(&mut self, val: u8) -> Result<(), RadioError> {
+        if !self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        self.interface
+            .write_reg_word(Reg8x(Register8x::SynchPeakAttenuation), val)
+    }
+
+    /// 8x only. Write the packet preamble settings register directly; an escape hatch for GFSK and FLRC
+    /// preamble tuning not covered by the packet params. Bits 6:4 hold the preamble length, which is also
+    /// written by `set_packet_params` from `GfskPreambleLen8x` or `FlrcPreambleLen`; so run this after it.
+    /// The DS doesn't document the other bits; preserve them by reading the register first, with
+    /// `interface.read_reg_word`. DS, Table 13-1.
+    pub fn set_preamble_settings_8x(&mut self, val: u8) -> Result<(), RadioError> {
+        if !self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        self.interface
+            .write_reg_word(Reg8x(Register8x::PacketPreambleSettings), val)
+    }
+
+    /// 6x only. Set pull-up or pull-down resistors on DIO1, DIO2, and DIO3, using the `DioxPullUpControl`
+    /// and `DioxPullDownControl` registers. Bit n of each controls DIOn. This isn't run on init, so pulls
+    /// remain at their reset values unless set here. DS, Table 12-1.
+    pub fn configure_dio_pulls(
+        &mut self,
+        dio1: DioPull,
+        dio2: DioPull,
+        dio3: DioPull,
+    ) -> Result<(), RadioError> {
+        if self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        let mut pull_up = self
+            .interface
+            .read_reg_word(Reg6x(Register6x::DioxPullUpControl))?;
+        let mut pull_down = self
+            .interface
+            .read_reg_word(Reg6x(Register6x::DioxPullDownControl))?;
+
+        for (i, pull) in [dio1, dio2, dio3].iter().enumerate() {
+            let bit = 1 << (i + 1);
+            pull_up &= !bit;
+            pull_down &= !bit;
+
+            match pull {
+                DioPull::None => (),
+                DioPull::Up => pull_up |= bit,
+                DioPull::Down => pull_down |= bit,
+            }
+        }
+
+        self.interface
+            .write_reg_word(Reg6x(Register6x::DioxPullUpControl), pull_up)?;
+        self.interface
+            .write_reg_word(Reg6x(Register6x::DioxPullDownControl), pull_down)
+    }
+
     /// 6x only. See DS, section 13.1.14. These settings should be hard-set to specific values.
     /// See Table 13-21: PA Operating Modes and Optimal Settings for how to set this.
     pub(crate) fn set_pa_config(&mut self) -> Result<(), RadioError> {
         match &self.config {
             RadioConfig::R6x(config) => {
-                let (duty_cycle, hp_max) = config.output_power.dutycycle_hpmax();
-                // Byte 3 is always 0 for sx1262 (1 for 1261). Byte 4 is always 1.
+                let power = config.output_power;
+                let (duty_cycle, hp_max) = power.dutycycle_hpmax();
+                // Byte 3 is 0 for sx1262 (1 for 1261). Byte 4 is always 1.
+                self.interface.write(&[
+                    OpCode::SetPAConfig as u8,
+                    duty_cycle,
+                    hp_max,
+                    power.device_sel(),
+                    1,
+                ])?;
+
                 self.interface
-                    .write(&[OpCode::SetPAConfig as u8, duty_cycle, hp_max, 0, 1])
+                    .write_reg_word(Reg6x(Register6x::OcpConfiguration), power.ocp())
             }
-            _ => unimplemented!(),
+            _ => Err(RadioError::Config),
         }
     }
 
@@ -303,11 +618,14 @@ impl Radio {
     /// 8x: 13db is max: power = 31 (0x1f)
     pub(crate) fn set_tx_params(&mut self) -> Result<(), RadioError> {
         let (power, ramp_time) = match &self.config {
-            RadioConfig::R6x(config) => {
-                (config.output_power as u8, config.ramp_time as u8) // Max power.
-            }
+            RadioConfig::R6x(config) => (
+                config.output_power.tx_params_power(),
+                config.ramp_time as u8,
+            ),
             RadioConfig::R8x(config) => {
-                assert!(config.output_power >= -18 && config.output_power <= 13);
+                if !(-18..=13).contains(&config.output_power) {
+                    return Err(RadioError::InvalidConfig(ConfigError::OutputPower));
+                }
                 ((config.output_power + 18) as u8, config.ramp_time as u8) // Max power.
             }
         };
@@ -316,61 +634,548 @@ impl Radio {
             .write(&[OpCode::SetTxParams as u8, power, ramp_time])
     }
 
+    /// Set output power in dBm, with the same API on both families, and send it to the radio. Returns the
+    /// power actually set. 6x: Rounds to the nearest `OutputPower6x` level on the PA currently configured
+    /// (ties round down). High power PA (sx1262): 14, 17, 20, or 22 dBm; supports 14 to 22 dBm. Low power
+    /// PA (sx1261): 10, 14, or 15 dBm; supports 10 to 15 dBm. 8x: Supports -18 to +13 dBm, in 1dB steps.
+    /// Returns `RadioError::InvalidConfig(ConfigError::OutputPower)` if out of range. Set `output_power` in
+    /// the config directly for precise control.
+    pub fn set_output_power_dbm(&mut self, dbm: i8) -> Result<i8, RadioError> {
+        match &mut self.config {
+            RadioConfig::R6x(config) => {
+                // An sx1261 is configured with a low power PA level; stay on that PA.
+                let low_power_pa = config.output_power.device_sel() == 1;
+                let (power, actual) = OutputPower6x::nearest(dbm, low_power_pa)
+                    .ok_or(RadioError::InvalidConfig(ConfigError::OutputPower))?;
+                config.output_power = power;
+
+                // The PA duty cycle and HP max depend on the output power.
+                self.set_pa_config()?;
+                self.set_tx_params()?;
+
+                Ok(actual)
+            }
+            RadioConfig::R8x(config) => {
+                if !(-18..=13).contains(&dbm) {
+                    return Err(RadioError::InvalidConfig(ConfigError::OutputPower));
+                }
+                config.output_power = dbm;
+
+                self.set_tx_params()?;
+
+                Ok(dbm)
+            }
+        }
+    }
+
+    /// 8x only. Configure the radio to transmit BLE packets, eg advertising PDUs for a beacon. DS, section 14.6.
+    /// Send PDUs with `send_payload`; advertising channels are at 2_402, 2_426, and 2_480 Mhz. `access_address`
+    /// is 0x8E89_BED6 for advertising, and `crc_init` is 0x55_5555. Only the lower 24 bits of `crc_init` are used.
+    pub fn configure_ble(&mut self, access_address: u32, crc_init: u32) -> Result<(), RadioError> {
+        match &mut self.config {
+            RadioConfig::R8x(config) => {
+                config.packet_type = PacketType8x::Ble;
+                // BLE requires 1Mb/s, modulation index 0.5, and BT 0.5.
+                config.modulation_params = ModulationParams8x::Gfsk(ModulationParamsGfsk8x {
+                    bitrate_bandwidth: GfskBitrateBandwidth8x::BR_1_0_BW_1_2,
+                    mod_index: GfskModIndex8x::MI_0_5,
+                    bt: FlrcBt::BT_0_5,
+                });
+                config.packet_params = PacketParams::Ble(Default::default());
+            }
+            RadioConfig::R6x(_) => return Err(RadioError::Config),
+        }
+
+        // The packet type must be changed in STDBY_RC mode.
+        self.set_op_mode(OperatingMode::StbyRc)?;
+        self.interface
+            .write_op_word(OpCode::SetPacketType, PacketType8x::Ble as u8)?;
+
+        self.set_rf_freq()?;
+        self.set_mod_params()?;
+        self.set_packet_params()?;
+
+        let aa = access_address.to_be_bytes();
+        let access_address_regs = [
+            Register8x::SyncAddress1Byte3,
+            Register8x::SyncAddress1Byte2,
+            Register8x::SyncAddress1Byte1,
+            Register8x::SyncAddress1Byte0,
+        ];
+        for (reg, byte) in access_address_regs.into_iter().zip(aa) {
+            self.interface.write_reg_word(Reg8x(reg), byte)?;
+        }
+
+        let crc = crc_init.to_be_bytes();
+        let crc_regs = [
+            Register8x::CrcPolynomialDefinitionLsb, // Shares an address with CRC seed byte 2.
+            Register8x::CrcPolynomialSeedByte1,
+            Register8x::CrcPolynomialSeedByte0,
+        ];
+        for (reg, byte) in crc_regs.into_iter().zip(&crc[1..]) {
+            self.interface.write_reg_word(Reg8x(reg), *byte)?;
+        }
+
+        Ok(())
+    }
+
+    /// 8x FLRC only. Set the CRC length, and the CRC polynomial and seed (initial value), eg to interoperate
+    /// with third-party devices. `poly` and `seed` must fit in the CRC length (2, 3, or 4 bytes), and in the
+    /// radio's 16-bit polynomial and seed registers (DS, Table 13-1); otherwise, this returns
+    /// `RadioError::Config`. With `CrcOff`, only the CRC length is written.
+    pub fn set_flrc_crc(&mut self, crc: FlrcCrc, seed: u32, poly: u32) -> Result<(), RadioError> {
+        let max = crc.max_value().min(FLRC_CRC_REG_MAX);
+
+        if max != 0 && (seed > max || poly > max) {
+            return Err(RadioError::Config);
+        }
+
+        match &mut self.config {
+            RadioConfig::R8x(config) if config.packet_type == PacketType8x::Flrc => {
+                match &mut config.packet_params {
+                    PacketParams::Flrc(p) => p.crc = crc,
+                    _ => return Err(RadioError::ParamMismatch),
+                }
+            }
+            _ => return Err(RadioError::Config),
+        }
+
+        self.set_packet_params()?;
+
+        if max == 0 {
+            return Ok(());
+        }
+
+        let poly = (poly as u16).to_be_bytes();
+        let seed = (seed as u16).to_be_bytes();
+        for (reg, byte) in [
+            (Register8x::CrcPolynomialDefinitionMsb, poly[0]),
+            (Register8x::CrcPolynomialDefinitionLsb, poly[1]),
+            (Register8x::CrcPolynomialSeedByte1, seed[0]),
+            (Register8x::CrcPolynomialSeedByte0, seed[1]),
+        ] {
+            self.interface.write_reg_word(Reg8x(reg), byte)?;
+        }
+
+        Ok(())
+    }
+
+    /// 8x FLRC only. Write sync word `index` (1 - 3) to its registers. DS, Table 13-1. Returns
+    /// `RadioError::Config` if the packet type isn't FLRC.
+    pub fn set_flrc_sync_word(&mut self, index: u8, word: u32) -> Result<(), RadioError> {
+        match &self.config {
+            RadioConfig::R8x(config) if config.packet_type == PacketType8x::Flrc => (),
+            _ => return Err(RadioError::Config),
+        }
+
+        // FLRC uses 32-bit sync words, in bytes 3 - 0 of each sync address.
+        let regs = match index {
+            1 => [
+                Register8x::SyncAddress1Byte3,
+                Register8x::SyncAddress1Byte2,
+                Register8x::SyncAddress1Byte1,
+                Register8x::SyncAddress1Byte0,
+            ],
+            2 => [
+                Register8x::SyncAddress2Byte3,
+                Register8x::SyncAddress2Byte2,
+                Register8x::SyncAddress2Byte1,
+                Register8x::SyncAddress2Byte0,
+            ],
+            3 => [
+                Register8x::SyncAddress3Byte3,
+                Register8x::SyncAddress3Byte2,
+                Register8x::SyncAddress3Byte1,
+                Register8x::SyncAddress3Byte0,
+            ],
+            _ => return Err(RadioError::Config),
+        };
+
+        for (reg, byte) in regs.into_iter().zip(word.to_be_bytes()) {
+            self.interface.write_reg_word(Reg8x(reg), byte)?;
+        }
+
+        self.flrc_sync_words_set |= 1 << (index - 1);
+        Ok(())
+    }
+
+    /// 8x FLRC only. Set which sync words the receiver correlates against, and update the packet params.
+    /// Each sync word the combo references must first be written with `set_flrc_sync_word`; otherwise,
+    /// this returns `RadioError::Config`, since the radio would miss packets. Enables the 32-bit sync word,
+    /// unless `combo` is `Disabled`.
+    pub fn set_flrc_sync_detection(&mut self, combo: FlrcSyncWordCombo) -> Result<(), RadioError> {
+        // The combo's upper nibble has a bit for each of sync words 1 - 3.
+        let required = (combo as u8) >> 4;
+        if required & !self.flrc_sync_words_set != 0 {
+            return Err(RadioError::Config);
+        }
+
+        match &mut self.config {
+            RadioConfig::R8x(config) if config.packet_type == PacketType8x::Flrc => {
+                match &mut config.packet_params {
+                    PacketParams::Flrc(p) => {
+                        p.sync_word_combo = combo;
+                        p.sync_word_len = match combo {
+                            FlrcSyncWordCombo::Disabled => FlrcSyncWordLen::NoSync,
+                            _ => FlrcSyncWordLen::P32,
+                        };
+                    }
+                    _ => return Err(RadioError::ParamMismatch),
+                }
+            }
+            _ => return Err(RadioError::Config),
+        }
+
+        self.set_packet_params()
+    }
+
+    /// Put the radio to sleep; the lowest current consumption possible. Wake it with `wake_from_sleep`.
+    pub fn sleep(&mut self, cfg: SleepConfig) -> Result<(), RadioError> {
+        self.set_op_mode(OperatingMode::Sleep(cfg))
+    }
+
+    /// Put the radio in standby mode, using the RC oscillator.
+    pub fn standby(&mut self) -> Result<(), RadioError> {
+        self.set_op_mode(OperatingMode::StbyRc)
+    }
+
+    /// Wake the radio from sleep. The radio wakes on a falling edge of CS, which we produce with a
+    /// GetStatus transaction, then wait for BUSY to go low; the radio is then in STDBY_RC.
+    /// We don't wait on BUSY before the transaction, since it's held high during sleep.
+    /// 6x: DS, section 13.1.1. 8x: section 11.6.1.
+    pub fn wake(&mut self) -> Result<(), RadioError> {
+        self.interface
+            .spi
+            .write(&[OpCode::GetStatus as u8, 0])
+            .map_err(|_| RadioError::Spi)?;
+
+        self.interface.wait_on_busy()
+    }
+
+    /// Wake the radio from sleep, and restore its configuration if required. After a warm start, the
+    /// configuration (packet type, modulation and packet params, sync word etc) is retained, but the data
+    /// buffer isn't. After a cold start, all configuration is lost, so we re-run `reinit` to reload it
+    /// from `self.config`. 6x: DS, section 9.3. 8x: section 11.6.1.
+    pub fn wake_from_sleep(&mut self) -> Result<(), RadioError> {
+        self.wake()?;
+
+        match self.sleep_cfg.take() {
+            Some(SleepConfig::ColdStart) => {
+                // This also clears the record of FLRC sync words written, since their registers are lost.
+                self.reinit()?;
+
+                if let RadioConfig::R6x(c) = &self.config {
+                    if c.calibrate_on_wake {
+                        self.calibrate()?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 6x only. Calibrate all blocks (RC oscillators, PLL, ADC), and the image rejection for the configured
+    /// frequency band. The radio must be in STDBY_RC. DS, sections 9.2.1, 13.1.12, and 13.1.13.
+    /// (8x calibrates automatically, and has no calibration command)
+    pub fn calibrate(&mut self) -> Result<(), RadioError> {
+        let rf_freq = match &self.config {
+            RadioConfig::R6x(c) => c.rf_freq,
+            RadioConfig::R8x(_) => return Err(RadioError::Config),
+        };
+
+        self.set_op_mode(OperatingMode::StbyRc)?;
+        self.interface
+            .write_op_word(OpCode::Calibrate, CALIBRATE_ALL_6X)?;
+
+        self.calibrate_image_6x(rf_freq)
+    }
+
+    /// 6x only. Calibrate image rejection for the band containing `rf_freq`. At power-on, the radio
+    /// calibrates for 902 - 928MHz; other bands, eg 433MHz ISM, have poor sensitivity until this runs.
+    /// DS, section 13.1.13.
+    pub(crate) fn calibrate_image_6x(&mut self, rf_freq: u32) -> Result<(), RadioError> {
+        let (f1, f2) = image_cal_bands_6x(rf_freq);
+        self.interface
+            .write(&[OpCode::CalibrateImage as u8, f1, f2])
+    }
+
+    /// Set LoRa channel activity detection parameters. Run `start_cad` to begin detection.
+    /// 6x: DS, section 13.4.7. 8x: DS, section 11.7.9; `CadExitMode::Rx` isn't available on 8x.
+    pub fn set_cad_params(&mut self, params: &CadParams) -> Result<(), RadioError> {
+        match self.config {
+            RadioConfig::R6x(_) => {
+                let (exit_mode, timeout) = match params.exit_mode {
+                    CadExitMode::StandbyRc => (0, [0; 3]),
+                    CadExitMode::Rx(timeout) => (1, time_bytes_6x(timeout)),
+                };
+
+                self.interface.write(&[
+                    OpCode::SetCADParams as u8,
+                    params.symbol_num.val_6x(),
+                    params.det_peak,
+                    params.det_min,
+                    exit_mode,
+                    timeout[0],
+                    timeout[1],
+                    timeout[2],
+                ])
+            }
+            RadioConfig::R8x(_) => {
+                if let CadExitMode::Rx(_) = params.exit_mode {
+                    return Err(RadioError::Config);
+                }
+
+                self.interface
+                    .write(&[OpCode::SetCADParams.val_8x()?, params.symbol_num.val_8x()])
+            }
+        }
+    }
+
     /// Sets the device into sleep mode; the lowest current consumption possible. Wake up by setting CS low.
     pub fn set_op_mode(&mut self, mode: OperatingMode) -> Result<(), RadioError> {
         match mode {
-            // todo: This behavior is 6x only. 8x is different (?)
             OperatingMode::Sleep(cfg) => {
-                self.interface
-                    // todo: Wake-up on RTC A/R.
-                    .write_op_word(OpCode::SetSleep, (cfg as u8) << 2)
+                // The retention bit is bit 2 on 6x, and bit 0 on 8x.
+                let word = match self.config {
+                    RadioConfig::R6x(_) => (cfg as u8) << 2,
+                    RadioConfig::R8x(_) => cfg as u8,
+                };
+                // todo: Wake-up on RTC A/R.
+                self.interface.write_op_word(OpCode::SetSleep, word)?;
+                self.sleep_cfg = Some(cfg);
+                Ok(())
             }
 
             OperatingMode::StbyRc => self.interface.write_op_word(OpCode::SetStandby, 0),
             OperatingMode::StbyOsc => self.interface.write_op_word(OpCode::SetStandby, 1),
-            OperatingMode::Fs => self.interface.write(&[OpCode::SetFS as u8]),
+            OperatingMode::Fs => {
+                self.interface.write(&[OpCode::SetFS as u8])?;
+                self.ensure_locked()
+            }
             OperatingMode::Tx(timeout) => {
                 let (op_code, to_bytes) = match self.config {
                     RadioConfig::R6x(_) => (OpCode::SetTx as u8, time_bytes_6x(timeout)),
-                    RadioConfig::R8x(_) => (OpCode::SetTx.val_8x(), time_bytes_8x(timeout)),
+                    RadioConfig::R8x(_) => (OpCode::SetTx.val_8x()?, time_bytes_8x(timeout)),
                 };
                 self.interface
-                    .write(&[op_code, to_bytes[0], to_bytes[1], to_bytes[2]])
+                    .write(&[op_code, to_bytes[0], to_bytes[1], to_bytes[2]])?;
+                self.ensure_locked()
             }
             OperatingMode::Rx(timeout) => {
                 let (op_code, to_bytes) = match self.config {
                     RadioConfig::R6x(_) => (OpCode::SetRx as u8, time_bytes_6x(timeout)),
-                    RadioConfig::R8x(_) => (OpCode::SetRx.val_8x(), time_bytes_8x(timeout)),
+                    RadioConfig::R8x(_) => (OpCode::SetRx.val_8x()?, time_bytes_8x(timeout)),
                 };
                 self.interface
-                    .write(&[op_code, to_bytes[0], to_bytes[1], to_bytes[2]])
+                    .write(&[op_code, to_bytes[0], to_bytes[1], to_bytes[2]])?;
+                self.ensure_locked()
+            }
+        }
+    }
+
+    /// Enter Rx duty cycle mode, alternating between Rx for `rx_ms`, and sleep for `sleep_ms`. This must be
+    /// sent from standby. 6x: DS, section 13.1.7. 8x: section 11.6.6.
+    pub(crate) fn set_rx_duty_cycle(
+        &mut self,
+        rx_ms: f32,
+        sleep_ms: f32,
+    ) -> Result<(), RadioError> {
+        match self.config {
+            RadioConfig::R6x(_) => {
+                let rx = time_bytes_6x(rx_ms);
+                let sleep = time_bytes_6x(sleep_ms);
+                self.interface.write(&[
+                    OpCode::SetRxDutyCycle as u8,
+                    rx[0],
+                    rx[1],
+                    rx[2],
+                    sleep[0],
+                    sleep[1],
+                    sleep[2],
+                ])
+            }
+            RadioConfig::R8x(_) => {
+                let b = duty_cycle_bytes_8x(rx_ms, sleep_ms);
+                self.interface.write(&[
+                    OpCode::SetRxDutyCycle.val_8x()?,
+                    b[0],
+                    b[1],
+                    b[2],
+                    b[3],
+                    b[4],
+                ])
             }
         }
     }
 }
 
+/// The frequency register value for `rf_freq`, in Hz. This uses integer math, since an f32 can't represent
+/// the 6x intermediate values exactly; we use u64 to prevent an overflow.
+fn rf_freq_raw(rf_freq: u32, r8x: bool) -> u32 {
+    let (f_xtal, shift) = if r8x {
+        (F_XTAL_8X, FREQ_SHIFT_8X)
+    } else {
+        (F_XTAL_6X, FREQ_SHIFT_6X)
+    };
+
+    (((rf_freq as u64) << shift) / f_xtal) as u32
+}
+
+/// The 6x GFSK bit rate and frequency deviation register values: br = 32 * F_XTAL / bit rate, and
+/// Fdev = fdev * 2^25 / F_XTAL. DS, section 13.4.5.1. Returns `RadioError::Config` if the bit rate is
+/// outside 600 - 300,000 b/s, or the deviation doesn't fit in its 24-bit register.
+fn gfsk_br_fdev_6x(m: &ModulationParamsGfsk6x) -> Result<(u32, u32), RadioError> {
+    if !(600..=300_000).contains(&m.bitrate) {
+        return Err(RadioError::Config);
+    }
+
+    let br = (32 * F_XTAL_6X / m.bitrate as u64) as u32;
+    let fdev = ((m.fdev as u64) << FREQ_SHIFT_6X) / F_XTAL_6X;
+
+    if fdev > 0xff_ffff {
+        return Err(RadioError::Config);
+    }
+
+    Ok((br, fdev as u32))
+}
+
 /// Convert a f32 time in ms to 3 24-but unsigned integer bytes, used with the radio's system. Used for
 /// sleep, and Rx duration.
 /// This is defined a few times in the datasheet, including section 13.1.4.
+/// 0xff_ffff sets continuous mode on Rx, so we cap the count at 0xff_fffe; about 262 seconds.
 pub fn time_bytes_6x(time_ms: f32) -> [u8; 3] {
     // Sleep Duration = sleepPeriod * 15.625 µs
-    let result = ((time_ms / TIMING_FACTOR_MS_6X) as u32).to_be_bytes();
+    let count = time_ms / TIMING_FACTOR_MS_6X;
+    let result = if count > MAX_PERIOD_COUNT_6X as f32 {
+        MAX_PERIOD_COUNT_6X
+    } else {
+        count as u32
+    }
+    .to_be_bytes();
     [result[1], result[2], result[3]]
 }
 
-/// Convert a f32 time in ms to 3 24-but unsigned integer bytes, used with the radio's system.
-///
-/// Note: This is more flexible than 6x's, but we, for now, hard set the period base to be the same
-/// as 6x.
+/// Convert a f32 time in ms to the 3 bytes used by the 8x for timeouts: a period base, and a 16-bit
+/// count of that base. We use the smallest of the four period bases that fits the requested time, for
+/// the best resolution; this allows timeouts up to about 262 seconds.
+/// Note that a count of 0 disables the timeout, and 0xffff sets continuous mode on Rx, so we cap the
+/// count at 0xfffe.
 /// See DS Table 11-24, and section 11.6.5.
 pub fn time_bytes_8x(time_ms: f32) -> [u8; 3] {
-    // Sleep Duration = PeriodBase * sleepPeriodBaseCount. (PeriodBase is what we set in the register)
-    // todo: QC order, etc
-    // let period_base = ((time_ms / TIMING_FACTOR_MS_6X) as u32).to_be_bytes();
-    let period_base = ((time_ms / TIMING_FACTOR_MS_6X) as u16).to_be_bytes();
-    // 0 in the first position defines th ebase period to be the 15.625 us value hard-coded for 6x.
+    let (base_byte, base_ms) = period_base_8x(time_ms);
+    let count = period_count_8x(time_ms, base_ms).to_be_bytes();
+    [base_byte, count[0], count[1]]
+}
 
-    // println!("PERIOD BASE: {:?}", period_base);
+/// Convert Rx and sleep periods, in ms, to the 5 bytes used by the 8x SetRxDutyCycle command: a period
+/// base, and a 16-bit count of it for each period. Both periods share a single base, so we pick the smallest
+/// that fits the longer of the two; a long sleep period costs resolution on a short Rx period. Both
+/// are capped at about 262 seconds. The Rx period is at least one period base, so it isn't rounded down to 0.
+/// DS, Table 11-24, and section 11.6.6.
+pub fn duty_cycle_bytes_8x(rx_ms: f32, sleep_ms: f32) -> [u8; 5] {
+    let (base_byte, base_ms) = period_base_8x(rx_ms.max(sleep_ms));
+    let rx = period_count_8x(rx_ms, base_ms).max(1).to_be_bytes();
+    let sleep = period_count_8x(sleep_ms, base_ms).to_be_bytes();
+    [base_byte, rx[0], rx[1], sleep[0], sleep[1]]
+}
 
-    [0x00, period_base[0], period_base[1]]
+/// The smallest 8x period base (register value, ms) that can represent `time_ms`, or the largest if none can.
+fn period_base_8x(time_ms: f32) -> (u8, f32) {
+    // Duration = PeriodBase * periodBaseCount.
+    for (base_byte, base_ms) in PERIOD_BASES_8X {
+        if time_ms / base_ms <= MAX_PERIOD_COUNT_8X as f32 {
+            return (base_byte, base_ms);
+        }
+    }
+    PERIOD_BASES_8X[3]
+}
+
+/// The count of `base_ms` periods in `time_ms`, capped at the maximum.
+fn period_count_8x(time_ms: f32, base_ms: f32) -> u16 {
+    let count = time_ms / base_ms;
+    if count > MAX_PERIOD_COUNT_8X as f32 {
+        // Longer than we can represent; use the maximum.
+        MAX_PERIOD_COUNT_8X
+    } else {
+        count as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_cal_bands() {
+        assert_eq!(image_cal_bands_6x(433_920_000), (0x6b, 0x6f));
+        assert_eq!(image_cal_bands_6x(450_000_000), (0x75, 0x81));
+        assert_eq!(image_cal_bands_6x(490_000_000), (0x75, 0x81));
+        assert_eq!(image_cal_bands_6x(780_000_000), (0xc1, 0xc5));
+        assert_eq!(image_cal_bands_6x(800_000_000), (0xd7, 0xdb));
+        assert_eq!(image_cal_bands_6x(868_000_000), (0xd7, 0xdb));
+        assert_eq!(image_cal_bands_6x(915_000_000), (0xe1, 0xe9));
+    }
+
+    #[test]
+    fn gfsk_br_fdev() {
+        let m = ModulationParamsGfsk6x::default();
+        // 32 * 32MHz / 50kb/s, and 25kHz * 2^25 / 32MHz; truncated.
+        assert_eq!(gfsk_br_fdev_6x(&m), Ok((0x00_5000, 0x00_6666)));
+
+        let slow = ModulationParamsGfsk6x {
+            bitrate: 599,
+            ..Default::default()
+        };
+        assert_eq!(gfsk_br_fdev_6x(&slow), Err(RadioError::Config));
+    }
+
+    #[test]
+    fn freq_raw() {
+        // 915MHz * 2^25 / 32MHz; exact.
+        assert_eq!(rf_freq_raw(915_000_000, false), 959_447_040);
+        assert_eq!(rf_freq_raw(868_100_000, false), 910_268_825);
+        // 2.4GHz * 2^18 / 52MHz = 12_098_953.8; truncated.
+        assert_eq!(rf_freq_raw(2_400_000_000, true), 12_098_953);
+    }
+
+    #[test]
+    fn time_bytes() {
+        // 15.625µs steps.
+        assert_eq!(time_bytes_6x(0.015_625), [0, 0, 1]);
+        assert_eq!(time_bytes_6x(1.), [0, 0, 64]);
+        assert_eq!(time_bytes_6x(1_000.), [0, 0xfa, 0x00]);
+        assert_eq!(time_bytes_6x(60_000.), [0x3a, 0x98, 0x00]);
+        // Capped below continuous mode.
+        assert_eq!(time_bytes_6x(5. * 60_000.), [0xff, 0xff, 0xfe]);
+
+        // 15.625µs base.
+        assert_eq!(time_bytes_8x(0.015_625), [0x00, 0, 1]);
+        assert_eq!(time_bytes_8x(1.), [0x00, 0, 64]);
+        // 62.5µs base.
+        assert_eq!(time_bytes_8x(1_500.), [0x01, 0x5d, 0xc0]);
+        // 1ms base.
+        assert_eq!(time_bytes_8x(60_000.), [0x02, 0xea, 0x60]);
+        // 4ms base.
+        assert_eq!(time_bytes_8x(4. * 60_000.), [0x03, 0xea, 0x60]);
+        // Capped below continuous mode.
+        assert_eq!(time_bytes_8x(5. * 60_000.), [0x03, 0xff, 0xfe]);
+    }
+
+    #[test]
+    fn duty_cycle_8x() {
+        // Both fit the smallest base.
+        assert_eq!(duty_cycle_bytes_8x(1., 10.), [0x00, 0, 64, 0x02, 0x80]);
+        // The shortest Rx period, with the longest sleep: The shared base is set by the sleep period, and the
+        // Rx period is held at one period base.
+        assert_eq!(
+            duty_cycle_bytes_8x(0.015_625, 262_000.),
+            [0x03, 0, 1, 0xff, 0xdc]
+        );
+        // Sleep past the maximum is capped.
+        assert_eq!(
+            duty_cycle_bytes_8x(8., 1_000_000.),
+            [0x03, 0, 2, 0xff, 0xfe]
+        );
+    }
 }
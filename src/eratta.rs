@@ -1,23 +1,37 @@
 //! Eratta workarounds
 
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
 use crate::{
-    params::LoraBandwidth6x,
-    shared::{RadioError, Register, Register6x},
-    PacketType, Radio, RadioConfig,
+    params::{InvertIq, LoraBandwidth6x},
+    shared::{RadioError, Register, Register6x, RX_GAIN_RETENTION_DEFAULT_6X},
+    PacketType6x, Radio, RadioConfig,
 };
 
-impl Radio {
-    /// (6x only) See DS, section 9.6: Receive (RX) Mode).
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
+    /// (6x only) See DS, section 9.6: Receive (RX) Mode). Applies `shared::RX_GAIN_RETENTION_DEFAULT_6X`.
     pub fn set_rxgain_retention(&mut self) -> Result<(), RadioError> {
+        let [v0, v1, v2] = RX_GAIN_RETENTION_DEFAULT_6X;
+        self.set_rx_gain_retention(v0, v1, v2)
+    }
+
+    /// (6x only) Set the Rx gain retention registers to custom values; eg when characterizing a board.
+    /// Init applies the DS-recommended values, `shared::RX_GAIN_RETENTION_DEFAULT_6X`; use those unless you have
+    /// a reason not to. DS, section 9.6.
+    pub fn set_rx_gain_retention(&mut self, v0: u8, v1: u8, v2: u8) -> Result<(), RadioError> {
+        if self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
         self.interface
-            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention0), 0x01)?;
+            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention0), v0)?;
         self.interface
-            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention1), 0x08)?;
+            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention1), v1)?;
         self.interface
-            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention2), 0xac)
+            .write_reg_word(Register::Reg6x(Register6x::RxGainRetention2), v2)
     }
 
-    /// (6x only) See DS, section 15.2.2.
+    /// (6x only) See DS, section 15.2.2. This concerns the PA, so applies to all packet types.
     pub fn tx_clamp_workaround(&mut self) -> Result<(), RadioError> {
         let val = self
             .interface
@@ -26,16 +40,17 @@ impl Radio {
             .write_reg_word(Register::Reg6x(Register6x::TxClampConfig), val | 0x1e)
     }
 
-    /// DS, section 16.1.2. Adapted from pseudocode there.
+    /// DS, section 16.1.2. Adapted from pseudocode there. The fix (clearing bit 2) is specific to LoRa
+    /// at 500kHz bandwidth; the DS specifies setting the bit for all other cases, including GFSK.
     /// (6x only)
     pub fn mod_quality_workaround(&mut self) -> Result<(), RadioError> {
-        let mut value = self
-            .interface
-            .read_reg_word(Register::Reg6x(Register6x::TxModulation))?;
-
         match &self.config {
             RadioConfig::R6x(config) => {
-                if config.packet_type == PacketType::Lora
+                let mut value = self
+                    .interface
+                    .read_reg_word(Register::Reg6x(Register6x::TxModulation))?;
+
+                if config.packet_type == PacketType6x::Lora
                     && config.modulation_params.mod_bandwidth == LoraBandwidth6x::BW_500
                 {
                     value &= 0xFB;
@@ -47,11 +62,27 @@ impl Radio {
                 self.interface
                     .write_reg_word(Register::Reg6x(Register6x::TxModulation), value)
             }
-            _ => unimplemented!(),
+            RadioConfig::R8x(_) => Err(RadioError::Config),
         }
     }
 
-    /// (6x only) See DS, section 15.3.2
+    /// (6x only) See DS, section 15.4.2. Run after each `SetPacketParams` in LoRa mode; the radio
+    /// otherwise loses packets when IQ polarity is inverted.
+    pub fn iq_polarity_workaround(&mut self, invert_iq: InvertIq) -> Result<(), RadioError> {
+        let val = self
+            .interface
+            .read_reg_word(Register::Reg6x(Register6x::IqPolaritySetup))?;
+
+        let val = match invert_iq {
+            InvertIq::Inverted => val & !0x04,
+            InvertIq::Standard => val | 0x04,
+        };
+
+        self.interface
+            .write_reg_word(Register::Reg6x(Register6x::IqPolaritySetup), val)
+    }
+
+    /// (6x only) See DS, section 15.3.2. LoRa implicit header mode only; `cleanup_rx` runs it in that case.
     /// "It is advised to add the following commands after ANY Rx with Timeout active sequence, which stop the RTC and clear the
     /// timeout event, if any."
     pub fn implicit_header_to_workaround(&mut self) -> Result<(), RadioError> {
@@ -5,43 +5,83 @@
 mod configure;
 mod eratta;
 pub mod params;
+mod ranging;
 pub mod shared;
 pub mod spi_interface;
 mod status;
+pub mod tx_queue;
 
 use defmt::println;
-use hal::dma::DmaChannel;
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
 
-// todo: Calibration on 8x?
 use crate::{
-    params::{ModulationParams8x, ModulationParamsLora6x, PacketParams, PacketParamsLora},
-    shared::{OpCode, RadioError, RadioPins, Register, Register::Reg8x, Register6x, Register8x},
-    spi_interface::{Interface, Spi_, RADIO_BUF_SIZE},
+    params::{
+        CadParams, CrcEnabled, FlrcCrc, GfskCrc6x, GfskCrc8x, LoraBandwidth6x, LoraCodingRate,
+        LoraHeaderType, LoraLdrOptimization, LoraSpreadingFactor, ModulationParams8x,
+        ModulationParamsGfsk6x, ModulationParamsLora6x, PacketParams, PacketParamsGfsk6x,
+        PacketParamsLora,
+    },
+    shared::{
+        ConfigError, OpCode, RadioError, RadioError::InvalidConfig, RadioPins, Register,
+        Register::Reg8x, Register6x, Register8x, MAX_ITERS,
+    },
+    spi_interface::{Interface, RADIO_BUF_SIZE},
 };
 
 // Error in the datasheet?
 const FIRMWARE_VERSION_8X_A: u16 = 0xA9B5;
 const FIRMWARE_VERSION_8X_B: u16 = 0xA9B7;
 
+// Frequency bands, in Hz.
+const FREQ_MIN_6X: u32 = 150_000_000;
+const FREQ_MAX_6X: u32 = 960_000_000;
+const FREQ_MIN_8X: u32 = 2_400_000_000;
+const FREQ_MAX_8X: u32 = 2_500_000_000;
+
+/// Time between RSSI readings in `measure_noise_floor`.
+const RSSI_SAMPLE_SPACING_US: u32 = 500;
+
+/// Tx timeout used by `transmit_beacon`, in ms. This only guards against the radio getting stuck in Tx; it
+/// must exceed the beacon's airtime.
+const BEACON_TX_TIMEOUT_MS: f32 = 500.;
+
+/// Reset value of the 8x `RxGain` register; power saving mode. Bits 7:6 select high sensitivity mode.
+const RX_GAIN_DEFAULT_8X: u8 = 0x25;
+
+/// Number of successive random number register reads XORed into each 4 bytes output by `fill_entropy`.
+const ENTROPY_MIX_READS: u8 = 4;
+/// Time between random number register reads in `fill_entropy`.
+const ENTROPY_SAMPLE_SPACING_US: u32 = 100;
+
 /// 6x DS, 13.4.2. Table 13-38.  The switch from one frame to another must be done in STDBY_RC mode.
-/// 8x: Table 11-42.
+/// The value is what's sent with `SetPacketType`.
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 #[allow(dead_code)]
-pub enum PacketType {
+pub enum PacketType6x {
+    /// (G)Fsk
+    Gfsk = 0,
+    Lora = 1,
+    /// Long Range FHSS. Tx only.
+    LrFhss = 3,
+}
+
+/// 8x: DS, Table 11-42. The switch from one frame to another must be done in STDBY_RC mode.
+/// The value is what's sent with `SetPacketType`.
+#[repr(u8)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+#[allow(dead_code)]
+pub enum PacketType8x {
     /// (G)Fsk
     Gfsk = 0,
     Lora = 1,
-    /// 8x only.
     Ranging = 2,
-    /// Long Range FHSS (FLRC on 8x)
-    LrFhssFlrc = 3,
-    /// 8x only.
+    Flrc = 3,
     Ble = 4,
 }
 
 #[repr(u16)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 /// (SX126x only(?) DS, table 12-1. Differentiate the LoRa signal for Public or Private network.
 /// set the `LoRa Sync word MSB and LSB values to this.
@@ -50,10 +90,36 @@ pub enum LoraNetwork {
     Private = 0x1424, // corresponds to sx127x 0x12
 }
 
+impl LoraNetwork {
+    /// Classify a sync word, eg one read with `get_lora_sync_word`. Returns `None` if it's neither
+    /// the public nor private value.
+    pub fn from_sync_word(word: u16) -> Option<Self> {
+        match word {
+            0x3444 => Some(Self::Public),
+            0x1424 => Some(Self::Private),
+            _ => None,
+        }
+    }
+}
+
+impl From<LoraNetwork> for u16 {
+    fn from(network: LoraNetwork) -> Self {
+        network as u16
+    }
+}
+
+impl TryFrom<u16> for LoraNetwork {
+    type Error = RadioError;
+
+    fn try_from(word: u16) -> Result<Self, Self::Error> {
+        Self::from_sync_word(word).ok_or(RadioError::Config)
+    }
+}
+
 /// DS, Table 13-41. Power ramp time. Titles correspond to ramp time in µs.
 /// todo: Figure out guidelines for setting this. The DS doesn't have much on it.
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum RampTime6x {
     R10 = 0,
@@ -69,7 +135,7 @@ pub enum RampTime6x {
 /// DS, Table 11-49. Power ramp time. Titles correspond to ramp time in µs.
 /// todo: Figure out guidelines for setting this. The DS doesn't have much on it.
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum RampTime8x {
     R02 = 0x0,
@@ -82,95 +148,199 @@ pub enum RampTime8x {
     R20 = 0xe0,
 }
 
-/// 6x: DS, Table 13-29. repr as u16, since we use the values to bit-mask a 16-bit integer.
-/// 8x: DS, Table 11-73. repr as u16, since we use the values to bit-mask a 16-bit integer.
-#[repr(u16)]
-#[derive(Clone, Copy, defmt::Format)]
+/// IRQ sources. Their bit positions in the 16-bit IRQ word differ between families; see `Irq::bit`.
+/// 6x: DS, Table 13-29. 8x: DS, Table 11-73.
+#[derive(Clone, Copy, PartialEq, defmt::Format, Debug)]
 #[allow(dead_code)]
 pub enum Irq {
-    TxDone = 0,
-    RxDone = 1,
-    PremableDetected = 2,
-    SyncWordValid = 3,
-    HeaderValid = 4,
-    HeaderErr = 5,
-    CrcErr = 6,
-    CadDone = 7,
-    CadDetected = 8,
-    Timeout = 9,
-    LrFhssHop = 14,
-    //8x only below.
+    TxDone,
+    RxDone,
+    PremableDetected,
+    SyncWordValid,
+    HeaderValid,
+    HeaderErr,
+    CrcErr,
+    CadDone,
+    CadDetected,
+    Timeout,
+    /// 6x only.
+    LrFhssHop,
+    /// 8x only.
     SyncWordError,
-    // todo, if you impl ranging.
-    //     RangingSlaveResponseDone = 7,
-    //     RangingSlaveRequestDiscard = 8,
-    //     RangingMasterResultValid = 9,
-    //     RangingMasterTimeut = 10,
-    //     RangingSlaveRequestValid = 11,
+    /// 8x only. Ranging; see `ranging.rs`.
+    RangingSlaveResponseDone,
+    RangingSlaveRequestDiscard,
+    RangingMasterResultValid,
+    RangingMasterTimeout,
+    RangingSlaveRequestValid,
 }
 
 impl Irq {
-    pub fn val_8x(&self) -> u16 {
-        match self {
-            Self::SyncWordValid => 2,
-            Self::SyncWordError => 3,
-            Self::CadDone => 12,
-            Self::CadDetected => 13,
-            Self::Timeout => 14,
-            Self::PremableDetected => 15, // Also used for Advanced Ranging Done.
-            _ => *self as u16,            // When the same as 6x.
+    /// The bit position in the IRQ word; the single source for both families. Returns `None` for
+    /// IRQs absent on that family. 6x: DS, Table 13-29. 8x: DS, Table 11-73.
+    pub fn bit(&self, r8x: bool) -> Option<u16> {
+        Some(match (self, r8x) {
+            (Self::TxDone, _) => 0,
+            (Self::RxDone, _) => 1,
+            (Self::HeaderValid, _) => 4,
+            (Self::HeaderErr, _) => 5,
+            (Self::CrcErr, _) => 6,
+
+            (Self::PremableDetected, false) => 2,
+            (Self::SyncWordValid, false) => 3,
+            (Self::CadDone, false) => 7,
+            (Self::CadDetected, false) => 8,
+            (Self::Timeout, false) => 9,
+            (Self::LrFhssHop, false) => 14,
+            (Self::SyncWordError, false) => return None,
+            (
+                Self::RangingSlaveResponseDone
+                | Self::RangingSlaveRequestDiscard
+                | Self::RangingMasterResultValid
+                | Self::RangingMasterTimeout
+                | Self::RangingSlaveRequestValid,
+                false,
+            ) => return None,
+
+            (Self::SyncWordValid, true) => 2,
+            (Self::SyncWordError, true) => 3,
+            (Self::RangingSlaveResponseDone, true) => 7,
+            (Self::RangingSlaveRequestDiscard, true) => 8,
+            (Self::RangingMasterResultValid, true) => 9,
+            (Self::RangingMasterTimeout, true) => 10,
+            (Self::RangingSlaveRequestValid, true) => 11,
+            (Self::CadDone, true) => 12,
+            (Self::CadDetected, true) => 13,
+            (Self::Timeout, true) => 14,
+            (Self::PremableDetected, true) => 15, // Also used for Advanced Ranging Done.
+            (Self::LrFhssHop, true) => return None,
+        })
+    }
+
+    /// This IRQ's mask in the IRQ word; 0 for IRQs absent on that family.
+    pub fn mask(&self, r8x: bool) -> u16 {
+        match self.bit(r8x) {
+            Some(b) => 1 << b,
+            None => 0,
         }
     }
 }
 
 /// Table 13-21
 /// These don't take into account the external PA, if applicable.
-/// Note that the values are listed for sx1262. They are hard-coded for high power PA selection.
-#[repr(u8)] // For storing in configs.
-#[derive(Clone, Copy)]
+/// The `Db` variants use the high power PA (sx1262), and the `Lp` variants, the low power PA (sx1261).
+#[derive(Clone, Copy, Default, defmt::Format)]
 pub enum OutputPower6x {
     /// 25mW
-    Db14 = 0x0e,
+    Db14,
     /// 50mW
-    Db17 = 0x11,
+    Db17,
     /// 100mW
-    Db20 = 0x14,
-    /// 158mW
-    Db22 = 0x16,
-}
-
-impl Default for OutputPower6x {
-    fn default() -> Self {
-        Self::Db22 // full power
-    }
+    Db20,
+    /// 158mW. The default; full power.
+    #[default]
+    Db22,
+    /// sx1261 only. 10mW
+    Lp10,
+    /// sx1261 only. 25mW
+    Lp14,
+    /// sx1261 only. 32mW
+    Lp15,
 }
 
 impl OutputPower6x {
     /// See datasheet, table 13-21
     /// For HP Max: 0 - 7. Do not set above 7, or you could cause early aging of the device. 7 sets max power,
-    ///  achieve +22dBm.
+    ///  achieve +22dBm. (HP Max is unused by the low power PA)
     pub fn dutycycle_hpmax(&self) -> (u8, u8) {
         match self {
             Self::Db14 => (0x02, 0x02),
             Self::Db17 => (0x02, 0x03),
             Self::Db20 => (0x03, 0x05),
             Self::Db22 => (0x04, 0x07),
+            Self::Lp10 => (0x01, 0x00),
+            Self::Lp14 => (0x04, 0x00),
+            Self::Lp15 => (0x06, 0x00),
+        }
+    }
+
+    /// The SetPaConfig device select byte: 0 for the high power PA (sx1262), and 1 for the low
+    /// power PA (sx1261).
+    pub fn device_sel(&self) -> u8 {
+        match self {
+            Self::Lp10 | Self::Lp14 | Self::Lp15 => 1,
+            _ => 0,
+        }
+    }
+
+    /// The power to send with SetTxParams, in dBm. Per Table 13-21, the low power PA settings use less
+    /// than their nominal output, since the PA config sets the rest.
+    pub fn tx_params_power(&self) -> u8 {
+        match self {
+            Self::Db14 => 0x0e,
+            Self::Db17 => 0x11,
+            Self::Db20 => 0x14,
+            Self::Db22 => 0x16,
+            Self::Lp10 => 0x0d,
+            Self::Lp14 | Self::Lp15 => 0x0e,
+        }
+    }
+
+    /// The level nearest `dbm` on the selected PA, and its power in dBm; ties round down. High power PA:
+    /// 14 to 22 dBm. Low power PA (sx1261): 10 to 15 dBm. Returns `None` if out of range.
+    pub(crate) fn nearest(dbm: i8, low_power_pa: bool) -> Option<(Self, i8)> {
+        let result = if low_power_pa {
+            match dbm {
+                10..=12 => (Self::Lp10, 10),
+                13..=14 => (Self::Lp14, 14),
+                15 => (Self::Lp15, 15),
+                _ => return None,
+            }
+        } else {
+            match dbm {
+                14..=15 => (Self::Db14, 14),
+                16..=18 => (Self::Db17, 17),
+                19..=21 => (Self::Db20, 20),
+                22 => (Self::Db22, 22),
+                _ => return None,
+            }
+        };
+        Some(result)
+    }
+
+    /// The over current protection limit, in 2.5mA steps: 140mA for the high power PA, and 60mA for the
+    /// low power PA. These are the values SetPaConfig sets; we write them explicitly after. DS,
+    /// section 13.1.14.
+    pub fn ocp(&self) -> u8 {
+        match self.device_sel() {
+            1 => 0x18,
+            _ => 0x38,
         }
     }
 }
 
-/// 6x only. DS, 13.1.15. This defines the mode the radio goes into after a successful Tx or Rx.
+/// This defines the mode the radio goes into after a successful Tx or Rx. 6x: DS, 13.1.15.
+/// 8x: Only `StdbyRc` and `Fs` are supported, using the SetAutoFs command; DS, Table 11-5.
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 pub enum FallbackMode {
     Fs = 0x40,
     StdbyXosc = 0x30,
     StdbyRc = 0x20,
 }
 
+/// 6x only. Pull resistor configuration for a DIO pin. Eg, pull unused DIOs to a defined level, to
+/// prevent floating inputs drawing current during sleep.
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+pub enum DioPull {
+    None,
+    Up,
+    Down,
+}
+
 // todo: 6x only? Can't tell
 /// 6x: DS, section 9.6: Receive (RX) Mode
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum RxMode {
     Continuous,
@@ -182,7 +352,7 @@ pub enum RxMode {
 /// 6x: DS, section 13.1.1. Table 13-2. For bit 2.
 /// 8x: DS, section 11.6.1. Table 11-17. For bit 0.
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 pub enum SleepConfig {
     /// "Ram flushed" on 8x.
     ColdStart = 0,
@@ -191,7 +361,7 @@ pub enum SleepConfig {
 }
 
 /// 6x DS, section 9. (And table 13-76) 8x: Table 11-5. (Called Circuit mode)
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum OperatingMode {
     /// In this mode, most of the radio internal blocks are powered down or in low power mode and optionally the RC64k clock
@@ -250,12 +420,61 @@ pub struct RxPacketStatusLora {
     pub signal_rssi: u8,
 }
 
+impl RxPacketStatusLora {
+    /// SNR, in dB. SnrPkt is in two's complement, multiplied by 4. 6x: DS, section 13.5.3.
+    /// 8x: DS, Table 11-68: Only negative SNR values are meaningful, so we clamp positive readings
+    /// to 0.
+    pub fn snr_db(&self, r8x: bool) -> f32 {
+        let snr = self.snr as i8 as f32 / 4.;
+        if r8x {
+            snr.min(0.)
+        } else {
+            snr
+        }
+    }
+}
+
+/// A summary of a received LoRa packet, eg for forwarding from a gateway or sniffer. Returned by
+/// `receive_report`.
+#[derive(defmt::Format)]
+pub struct RxReport {
+    pub payload_len: u8,
+    /// Average RSSI over the packet, in dBm.
+    pub rssi_dbm: f32,
+    pub snr_db: f32,
+    /// Frequency error, in Hz. 8x only; the 6x doesn't report it for LoRa.
+    pub freq_error_hz: Option<i32>,
+    pub crc_ok: bool,
+}
+
 /// 6x only: DS, section 13.5.4. Table 13-82
+/// GetStats returns three counters. The third counts header errors in LoRa mode, and length errors in
+/// GFSK mode; we populate the field matching the configured packet type, and leave the other at 0.
+#[derive(defmt::Format)]
 pub struct RxStatistics6x {
     pub status: u8,
     pub num_received: u16,
     pub num_crc_error: u16,
+    /// GFSK only.
     pub num_length_error: u16,
+    /// LoRa only.
+    pub num_header_error: u16,
+}
+
+impl RxStatistics6x {
+    /// The packet error rate, from 0 to 1: errors / (received + errors), where errors are CRC errors, and
+    /// length errors (GFSK) or header errors (LoRa). These share a counter on the radio, so only one is
+    /// populated. Returns 0 if no packets were counted.
+    pub fn packet_error_rate(&self) -> f32 {
+        let errors =
+            self.num_crc_error as u32 + self.num_length_error as u32 + self.num_header_error as u32;
+        let total = self.num_received as u32 + errors;
+
+        if total == 0 {
+            return 0.;
+        }
+        errors as f32 / total as f32
+    }
 }
 
 /// (6x): DS, section 13.5.1. 8x: Table 11-5
@@ -269,6 +488,10 @@ pub enum CommandStatus {
     DataAvailable = 2,
     /// A transaction from host took too long to complete and triggered an internal watchdog. The watchdog mechanism can be disabled by host; it
     /// is meant to ensure all outcomes are flagged to the host MCU
+    ///
+    /// Note: Neither DS documents a command or register for disabling this watchdog, so we don't expose
+    /// one. If spurious timeouts occur, eg while single-stepping in a debugger, avoid halting mid-transaction.
+    /// This status is also reported after an Rx timeout, where `cleanup_rx` treats it as "no packet".
     CommandTimeout = 3,
     /// Processor was unable to process command either because of an invalid opcode or because an incorrect number of parameters has been
     /// provided.
@@ -284,13 +507,21 @@ pub enum CommandStatus {
 /// It also includes consumption, RFIO schematics etc. Use `Shared RFIO`, vice a switch; breaks the calculator.
 #[derive(Clone)]
 pub struct RadioConfig6x {
-    pub packet_type: PacketType,
+    pub packet_type: PacketType6x,
     /// RF frequency in Hz.
     pub rf_freq: u32,
+    /// Frequency-division duplex: Stored Tx and Rx frequencies in Hz, used by `send_payload_fdd` and
+    /// `receive_fdd`. `None` uses `rf_freq`.
+    pub tx_freq: Option<u32>,
+    pub rx_freq: Option<u32>,
     pub use_dio2_as_rfswitch: bool,
     pub dc_dc_enabled: bool,
     pub modulation_params: ModulationParamsLora6x,
+    /// Used in place of `modulation_params` when `packet_type` is GFSK.
+    pub modulation_params_gfsk: ModulationParamsGfsk6x,
     pub packet_params: PacketParamsLora,
+    /// Used in place of `packet_params` when `packet_type` is GFSK.
+    pub packet_params_gfsk: PacketParamsGfsk6x,
     /// Timeouts, in ms.
     pub tx_timeout: f32,
     pub rx_timeout: f32,
@@ -298,25 +529,104 @@ pub struct RadioConfig6x {
     pub ramp_time: RampTime6x,
     pub lora_network: LoraNetwork,
     pub output_power: OutputPower6x,
+    /// LoRa only. If set, `cleanup_rx` rejects packets received with an SNR (dB) below this.
+    pub min_snr_db: Option<f32>,
+    /// LoRa only. If set, `cleanup_rx` rejects packets received with an RSSI (dBm) below this; eg to
+    /// discard weak, spurious decodes in continuous receive mode.
+    pub min_rssi_dbm: Option<f32>,
+    /// If set, `wake_from_sleep` runs `calibrate` after a cold start. This restores sensitivity after deep
+    /// sleep, at the cost of a few ms and some energy per wake. Warm starts never recalibrate.
+    pub calibrate_on_wake: bool,
+    /// If set, check for a PLL lock failure after entering FS, Tx, or Rx; see `ensure_locked`. This adds a
+    /// device errors read to every Tx and Rx, so is off by default.
+    pub check_pll_lock: bool,
 }
 
 impl Default for RadioConfig6x {
     fn default() -> Self {
         Self {
-            packet_type: PacketType::Lora,
+            packet_type: PacketType6x::Lora,
             rf_freq: 915_000_000,
+            tx_freq: None,
+            rx_freq: None,
             use_dio2_as_rfswitch: true,
             dc_dc_enabled: true,
             modulation_params: Default::default(),
+            modulation_params_gfsk: Default::default(),
             packet_params: Default::default(),
+            packet_params_gfsk: Default::default(),
             tx_timeout: 0., // todo: Calculate this based on packet and mod params?
             rx_timeout: 0.,
             fallback_mode: FallbackMode::StdbyRc,
             ramp_time: RampTime6x::R200, // todo: What should this be?
             lora_network: LoraNetwork::Private,
             output_power: OutputPower6x::Db22,
+            min_snr_db: None,
+            min_rssi_dbm: None,
+            calibrate_on_wake: false,
+            check_pll_lock: false,
+        }
+    }
+}
+
+/// Regional presets, using LoRaWAN-style settings: 125kHz bandwidth, 4/5 coding rate, a 12-symbol preamble,
+/// and the public sync word. These are starting points; check your local regulations for the channel, power,
+/// and duty cycle limits that apply.
+impl RadioConfig6x {
+    fn lorawan_preset(
+        rf_freq: u32,
+        spreading_factor: LoraSpreadingFactor,
+        output_power: OutputPower6x,
+    ) -> Self {
+        // LDRO is required for SF11 and SF12 at 125kHz, due to the long symbol time.
+        let low_data_rate_optimization = match spreading_factor {
+            LoraSpreadingFactor::SF11 | LoraSpreadingFactor::SF12 => LoraLdrOptimization::Enabled,
+            _ => LoraLdrOptimization::Disabled,
+        };
+
+        Self {
+            rf_freq,
+            modulation_params: ModulationParamsLora6x {
+                mod_bandwidth: LoraBandwidth6x::BW_125,
+                spreading_factor,
+                coding_rate: LoraCodingRate::CR_4_5,
+                low_data_rate_optimization,
+            },
+            packet_params: PacketParamsLora {
+                preamble_len: 12,
+                ..Default::default()
+            },
+            lora_network: LoraNetwork::Public,
+            output_power,
+            ..Default::default()
         }
     }
+
+    /// US915, 902.3Mhz (uplink channel 0), SF7.
+    pub fn us915_sf7_bw125() -> Self {
+        Self::lorawan_preset(902_300_000, LoraSpreadingFactor::SF7, OutputPower6x::Db22)
+    }
+
+    /// US915, 902.3Mhz (uplink channel 0), SF10; the longest range allowed by the US915 dwell time limit.
+    pub fn us915_sf10_bw125() -> Self {
+        Self::lorawan_preset(902_300_000, LoraSpreadingFactor::SF10, OutputPower6x::Db22)
+    }
+
+    /// EU868, 868.1Mhz, SF7. 14dBm, the EU868 limit.
+    pub fn eu868_sf7_bw125() -> Self {
+        Self::lorawan_preset(868_100_000, LoraSpreadingFactor::SF7, OutputPower6x::Db14)
+    }
+
+    /// EU868, 868.1Mhz, SF12; the longest range. 14dBm, the EU868 limit. Mind the 1% duty cycle limit, given
+    /// the long airtime.
+    pub fn eu868_sf12_bw125() -> Self {
+        Self::lorawan_preset(868_100_000, LoraSpreadingFactor::SF12, OutputPower6x::Db14)
+    }
+
+    /// AS923, 923.2Mhz, SF7. 14dBm, to stay under the 16dBm EIRP limit.
+    pub fn as923_sf7_bw125() -> Self {
+        Self::lorawan_preset(923_200_000, LoraSpreadingFactor::SF7, OutputPower6x::Db14)
+    }
 }
 
 /// See [this interactive tool for info on how these parameters effect OTA time etc](https://www.semtech.com/design-support/lora-calculator)
@@ -324,9 +634,17 @@ impl Default for RadioConfig6x {
 #[derive(Clone)]
 pub struct RadioConfig8x {
     // todo: Integrate mod and packet params into this enum?
-    pub packet_type: PacketType,
+    pub packet_type: PacketType8x,
     // RF frequency in Hz.
     pub rf_freq: u32,
+    /// Frequency-division duplex: Stored Tx and Rx frequencies in Hz, used by `send_payload_fdd` and
+    /// `receive_fdd`. `None` uses `rf_freq`.
+    pub tx_freq: Option<u32>,
+    pub rx_freq: Option<u32>,
+    /// Use the DC-DC regulator if true; the LDO if false. DC-DC draws less current, but requires the
+    /// inductor to be fitted, and may add spurs. The LDO draws more current, and is cleaner on boards
+    /// with noisy supplies. Defaults to false; the LDO is the radio's reset state, and works on any board.
+    /// Only enable this if your board has the DC-DC inductor; without it, the radio stops working.
     pub dc_dc_enabled: bool,
     pub modulation_params: ModulationParams8x,
     pub packet_params: PacketParams,
@@ -336,51 +654,276 @@ pub struct RadioConfig8x {
     pub ramp_time: RampTime8x,
     /// In dBm. Ranges from -18 to +13. Defaults to max power.
     pub output_power: i8, // pub lora_network: LoraNetwork,
+    /// LoRa only. If set, `cleanup_rx` rejects packets received with an SNR (dB) below this. Positive
+    /// SNR readings are clamped to 0, so set this at or below 0; see `RxPacketStatusLora::snr_db`.
+    pub min_snr_db: Option<f32>,
+    /// LoRa only. If set, `cleanup_rx` rejects packets received with an RSSI (dBm) below this; eg to
+    /// discard weak, spurious decodes in continuous receive mode.
+    pub min_rssi_dbm: Option<f32>,
+    /// LoRa only. Written to the frequency error correction register after setting modulation params;
+    /// DS, note below Table 14-47. Defaults to enabled (0x1). Some setups that use the frequency error
+    /// indicator downstream report better results with it disabled.
+    pub freq_error_correction: bool,
+    /// The mode the radio goes into after Tx or Rx. Staying in FS reduces turnaround latency for the next
+    /// operation, at the cost of current draw. `StdbyXosc` isn't supported on 8x.
+    pub fallback_mode: FallbackMode,
 }
 
 impl Default for RadioConfig8x {
     fn default() -> Self {
         Self {
-            packet_type: PacketType::Lora,
+            packet_type: PacketType8x::Lora,
             rf_freq: 2_400_000_000,
-            dc_dc_enabled: true,
+            tx_freq: None,
+            rx_freq: None,
+            dc_dc_enabled: false,
             modulation_params: Default::default(),
             packet_params: Default::default(),
             tx_timeout: 0., // todo: Calculate this based on packet and mod params?
             rx_timeout: 0.,
             ramp_time: RampTime8x::R10, // todo: What should this be?
             output_power: 13,
+            min_snr_db: None,
+            min_rssi_dbm: None,
+            freq_error_correction: true,
+            fallback_mode: FallbackMode::StdbyRc,
         }
     }
 }
 
-#[derive(Clone)]
+// The config structs format manually, to show frequency in MHz.
+impl defmt::Format for RadioConfig6x {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "RadioConfig6x {{ packet_type: {}, rf_freq: {} MHz, tx_freq: {}, rx_freq: {}, mod params: {}, \
+            packet params: {}, packet params GFSK: {}, output_power: {}, ramp_time: {}, network: {}, fallback: {}, \
+            dc_dc: {}, dio2 rf switch: {}, tx_timeout: {} ms, rx_timeout: {} ms, min_snr: {} dB, \
+            min_rssi: {} dBm, calibrate_on_wake: {}, check_pll_lock: {} }}",
+            self.packet_type,
+            self.rf_freq as f32 / 1_000_000.,
+            self.tx_freq,
+            self.rx_freq,
+            self.modulation_params,
+            self.packet_params,
+            self.packet_params_gfsk,
+            self.output_power,
+            self.ramp_time,
+            self.lora_network,
+            self.fallback_mode,
+            self.dc_dc_enabled,
+            self.use_dio2_as_rfswitch,
+            self.tx_timeout,
+            self.rx_timeout,
+            self.min_snr_db,
+            self.min_rssi_dbm,
+            self.calibrate_on_wake,
+            self.check_pll_lock,
+        )
+    }
+}
+
+impl defmt::Format for RadioConfig8x {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "RadioConfig8x {{ packet_type: {}, rf_freq: {} MHz, tx_freq: {}, rx_freq: {}, mod params: {}, \
+            packet params: {}, output_power: {} dBm, ramp_time: {}, dc_dc: {}, tx_timeout: {} ms, \
+            rx_timeout: {} ms, min_snr: {} dB, min_rssi: {} dBm, freq_error_correction: {}, \
+            fallback_mode: {} }}",
+            self.packet_type,
+            self.rf_freq as f32 / 1_000_000.,
+            self.tx_freq,
+            self.rx_freq,
+            self.modulation_params,
+            self.packet_params,
+            self.output_power,
+            self.ramp_time,
+            self.dc_dc_enabled,
+            self.tx_timeout,
+            self.rx_timeout,
+            self.min_snr_db,
+            self.min_rssi_dbm,
+            self.freq_error_correction,
+            self.fallback_mode,
+        )
+    }
+}
+
+#[derive(Clone, defmt::Format)]
 pub enum RadioConfig {
     R6x(RadioConfig6x),
     R8x(RadioConfig8x),
 }
 
-pub struct Radio {
-    pub interface: Interface,
+impl RadioConfig {
+    /// Check the config for invalid values and inconsistent fields; run by `Radio::new` before any SPI
+    /// traffic. Returns `RadioError::InvalidConfig`, with the first problem found.
+    pub fn validate(&self) -> Result<(), RadioError> {
+        let lora_params = match self {
+            RadioConfig::R6x(c) => {
+                // The FDD frequencies are optional; check them if set.
+                for freq in [Some(c.rf_freq), c.tx_freq, c.rx_freq]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !(FREQ_MIN_6X..=FREQ_MAX_6X).contains(&freq) {
+                        return Err(InvalidConfig(ConfigError::FreqOutOfBand));
+                    }
+                }
+
+                match c.packet_type {
+                    PacketType6x::Lora => {
+                        c.modulation_params
+                            .validate(c.rf_freq)
+                            .map_err(|_| InvalidConfig(ConfigError::LoraModulation))?;
+
+                        if c.packet_params.preamble_len < configure::LORA_PREAMBLE_MIN_6X {
+                            return Err(InvalidConfig(ConfigError::PreambleLen));
+                        }
+                        Some(&c.packet_params)
+                    }
+                    PacketType6x::Gfsk => {
+                        if c.packet_params_gfsk.preamble_len == 0 {
+                            return Err(InvalidConfig(ConfigError::PreambleLen));
+                        }
+                        None
+                    }
+                    PacketType6x::LrFhss => None,
+                }
+            }
+            RadioConfig::R8x(c) => {
+                for freq in [Some(c.rf_freq), c.tx_freq, c.rx_freq]
+                    .into_iter()
+                    .flatten()
+                {
+                    if !(FREQ_MIN_8X..=FREQ_MAX_8X).contains(&freq) {
+                        return Err(InvalidConfig(ConfigError::FreqOutOfBand));
+                    }
+                }
+
+                if !(-18..=13).contains(&c.output_power) {
+                    return Err(InvalidConfig(ConfigError::OutputPower));
+                }
+
+                if matches!(c.fallback_mode, FallbackMode::StdbyXosc) {
+                    return Err(InvalidConfig(ConfigError::FallbackMode));
+                }
+
+                // There are no ranging modulation or packet params to send yet.
+                if c.packet_type == PacketType8x::Ranging {
+                    return Err(InvalidConfig(ConfigError::PacketType));
+                }
+
+                let params_match = match c.packet_type {
+                    PacketType8x::Gfsk => matches!(
+                        (&c.modulation_params, &c.packet_params),
+                        (ModulationParams8x::Gfsk(_), PacketParams::Gfsk(_))
+                    ),
+                    PacketType8x::Ble => matches!(
+                        (&c.modulation_params, &c.packet_params),
+                        (ModulationParams8x::Gfsk(_), PacketParams::Ble(_))
+                    ),
+                    PacketType8x::Lora | PacketType8x::Ranging => matches!(
+                        (&c.modulation_params, &c.packet_params),
+                        (ModulationParams8x::Lora(_), PacketParams::Lora(_))
+                    ),
+                    PacketType8x::Flrc => matches!(
+                        (&c.modulation_params, &c.packet_params),
+                        (ModulationParams8x::Flrc(_), PacketParams::Flrc(_))
+                    ),
+                };
+                if !params_match {
+                    return Err(InvalidConfig(ConfigError::ParamMismatch));
+                }
+
+                match &c.packet_params {
+                    PacketParams::Lora(p) => {
+                        // The preamble length is sent as a mantissa and exponent; see `set_packet_params`.
+                        if configure::lora_preamble_8x(p.preamble_len).is_none() {
+                            return Err(InvalidConfig(ConfigError::PreambleLen));
+                        }
+                        Some(p)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        if let Some(p) = lora_params {
+            if p.header_type == LoraHeaderType::FixedLength && p.payload_len == 0 {
+                return Err(InvalidConfig(ConfigError::ImplicitPayloadLen));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the packet params for the configured packet type have CRC enabled. BLE always uses a CRC.
+    pub(crate) fn crc_enabled(&self) -> bool {
+        match self {
+            Self::R6x(c) => match c.packet_type {
+                PacketType6x::Gfsk => !matches!(c.packet_params_gfsk.crc, GfskCrc6x::CrcOff),
+                _ => matches!(c.packet_params.crc_enabled, CrcEnabled::Enabled),
+            },
+            Self::R8x(c) => match &c.packet_params {
+                PacketParams::Lora(p) => matches!(p.crc_enabled, CrcEnabled::Enabled),
+                PacketParams::Flrc(p) => !matches!(p.crc, FlrcCrc::CrcOff),
+                PacketParams::Gfsk(p) => !matches!(p.crc, GfskCrc8x::CrcOff),
+                PacketParams::Ble(_) => true,
+            },
+        }
+    }
+}
+
+/// A payload to transmit: Either a slice of the payload alone, or a staged buffer, with 2 bytes reserved
+/// for the opcode and offset preceding the payload.
+enum TxPayload<'a> {
+    Slice(&'a [u8]),
+    Staged(&'a mut [u8]),
+}
+
+impl TxPayload<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Slice(p) => p.len(),
+            Self::Staged(buf) => buf.len() - 2,
+        }
+    }
+}
+
+pub struct Radio<SPI, D> {
+    pub interface: Interface<SPI, D>,
     pub config: RadioConfig,
+    /// Set when entering sleep, so `wake_from_sleep` knows if configuration was lost.
+    sleep_cfg: Option<SleepConfig>,
+    /// 8x FLRC. Bit n - 1 is set when sync word n has been written with `set_flrc_sync_word`.
+    flrc_sync_words_set: u8,
+    /// Tx and Rx base addresses in the data buffer. Set with `set_buffer_base`.
+    tx_base: u8,
+    rx_base: u8,
+    /// If set, called with the raw status byte when the radio reports an unexpected or invalid status, in
+    /// place of printing it; eg to route diagnostics to your own telemetry. The error is still returned.
+    pub status_callback: Option<fn(u8)>,
 }
 
-impl Radio {
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
     /// Initialize the radio. See DS section 14.5: Issuing Commands in the Right Order.
     ///
     /// Most of the commands can be sent in any order except for the radio configuration commands which will set the radio in
     /// the proper operating mode.
     /// ... (See inline comments prior to the mandatory order of the first 3 steps)
     /// If this order is not respected, the behavior of the device could be unexpected.
+    ///
+    /// `spi` handles chip select. To share one SPI peripheral between multiple radios, pass each
+    /// one a `SpiDevice` from a bus-sharing wrapper, with its own CS pin. `delay` is used for reset timing.
     pub fn new(
         config: RadioConfig,
-        spi: Spi_,
+        spi: SPI,
         pins: RadioPins,
-        tx_ch: DmaChannel,
-        rx_ch: DmaChannel,
+        delay: D,
     ) -> Result<Self, RadioError> {
-        let tx_addr = 0;
-        let rx_addr = 0;
+        config.validate()?;
 
         let r8x = matches!(config, RadioConfig::R8x(_));
 
@@ -388,20 +931,40 @@ impl Radio {
             config,
             interface: Interface {
                 spi,
+                delay,
                 pins,
-                tx_ch,
-                rx_ch,
                 read_buf: [0; RADIO_BUF_SIZE],
-                write_buf: [0; RADIO_BUF_SIZE],
                 rx_payload_len: 0,
                 rx_payload_start: 0,
+                rx_read_ptr: None,
                 r8x,
+                busy_debounce: 1,
             },
+            sleep_cfg: None,
+            flrc_sync_words_set: 0,
+            tx_base: 0,
+            rx_base: 0,
+            status_callback: None,
         };
 
+        result.reinit()?;
+
+        Ok(result)
+    }
+
+    /// Reset the radio (6x only; 8x checks its firmware version instead), and re-run the full configuration
+    /// sequence from `new`, using the stored config. Use this to recover a wedged radio, eg after a brownout or
+    /// device error. This ends in the same state as a freshly-created `Radio`.
+    pub fn reinit(&mut self) -> Result<(), RadioError> {
+        self.interface.rx_payload_len = 0;
+        self.interface.rx_payload_start = 0;
+        self.interface.rx_read_ptr = Some(self.rx_base);
+        // Assume the FLRC sync word registers were lost, eg in a cold start; they must be written again.
+        self.flrc_sync_words_set = 0;
+
         // We use this firmware version as a sanity check.
-        if r8x {
-            let firmware_version = result
+        if self.interface.r8x {
+            let firmware_version = self
                 .interface
                 .read_reg_word_16(Reg8x(Register8x::FirmwareVersions))?;
             if ![FIRMWARE_VERSION_8X_A, FIRMWARE_VERSION_8X_B].contains(&firmware_version) {
@@ -409,7 +972,7 @@ impl Radio {
             }
         } else {
             // Removed on 8x, due to using multiple radios on Meerkat.
-            result.interface.reset();
+            self.interface.reset();
         }
 
         // DS, section 9.1:
@@ -419,122 +982,181 @@ impl Radio {
         // stage the BUSY line goes down and the device is ready to accept commands.
         // "
 
-        result.interface.wait_on_busy()?;
+        self.interface.wait_on_busy()?;
 
         // Note: This is required to change some settings, like packet type.
-        result.set_op_mode(OperatingMode::StbyRc)?;
+        self.set_op_mode(OperatingMode::StbyRc)?;
 
         // Make sure we're in STDBY_RC mode when setting packet type.
         // "it is mandatory to set the radio protocol using the command SetPacketType(...) as a first
         // step before issuing any other radio configuration commands."
 
-        let packet_type = match result.config {
-            RadioConfig::R6x(ref config) => config.packet_type,
-            RadioConfig::R8x(ref config) => config.packet_type,
+        let packet_type = match self.config {
+            RadioConfig::R6x(ref config) => config.packet_type as u8,
+            RadioConfig::R8x(ref config) => config.packet_type as u8,
         };
 
-        result
-            .interface
-            .write_op_word(OpCode::SetPacketType, packet_type as u8)?;
+        self.interface
+            .write_op_word(OpCode::SetPacketType, packet_type)?;
 
         // Note: In the Tx/Rx recipes in the DS, this is before setting mod parameters; but it's not listed
         // this way in the part on section 9.1.
 
-        result.set_rf_freq()?;
+        self.set_rf_freq()?;
 
         // "In a second step, the user should define the modulation
         // parameter according to the chosen protocol with the command SetModulationParams(...)."
-        result.set_mod_params()?;
+        self.set_mod_params()?;
 
         // Finally, the user should then
         // select the packet format with the command SetPacketParams(...).
-        result.set_packet_params()?;
+        self.set_packet_params()?;
 
-        if let RadioConfig::R6x(_) = result.config {
-            result.set_rxgain_retention()?;
-            result.tx_clamp_workaround()?;
+        if let RadioConfig::R6x(_) = self.config {
+            self.set_rxgain_retention()?;
+            self.tx_clamp_workaround()?;
         }
 
-        result.set_tx_params()?;
+        self.set_tx_params()?;
 
-        result
-            .interface
-            .write(&[OpCode::SetBufferBaseAddress as u8, tx_addr, rx_addr])?;
+        self.write_buffer_base()?;
 
-        match result.config {
+        match self.config {
             RadioConfig::R6x(ref config) => {
                 // prevents borrow mut error
-                let (dc_dc, fallback, dio, network) = (
+                let (dc_dc, fallback, dio, network, rf_freq) = (
                     config.dc_dc_enabled,
                     config.fallback_mode,
                     config.use_dio2_as_rfswitch,
                     config.lora_network,
+                    config.rf_freq,
                 );
 
                 // Use the LDO, or DC-DC setup as required, based on hardware config.
-                result
-                    .interface
+                self.interface
                     .write_op_word(OpCode::SetRegulatorMode, dc_dc as u8)?;
 
-                result.set_pa_config()?;
+                // The power-on image calibration is for 902 - 928MHz; calibrate for the configured band.
+                self.calibrate_image_6x(rf_freq)?;
 
-                result
-                    .interface
+                self.set_pa_config()?;
+
+                self.interface
                     .write_op_word(OpCode::SetRxTxFallbackMode, fallback as u8)?;
 
-                result
-                    .interface
+                self.interface
                     .write_op_word(OpCode::SetDIO2AsRfSwitchCtrl, dio as u8)?;
 
                 // Note: Not required if private due to the reset value.
-                result.set_sync_word(network)?;
+                self.set_sync_word(network)?;
             }
             // See DS, section 14.4: LoRa Operation, and similar.
             RadioConfig::R8x(ref config) => {
-                // todo: This is breakigngs things...
-                // result
-                //     .interface
-                //     .write_op_word(OpCode::SetRegulatorMode, config.dc_dc_enabled as u8)?;
+                // prevents borrow mut error
+                let (dc_dc, auto_fs) = (
+                    config.dc_dc_enabled,
+                    matches!(config.fallback_mode, FallbackMode::Fs),
+                );
+
+                // 0 selects the LDO; 1, DC-DC. This must be set in STDBY_RC, which we're still in.
+                // DS, section 11.6.4. (Same opcode as 6x)
+                self.interface
+                    .write_op_word(OpCode::SetRegulatorMode, dc_dc as u8)?;
+
+                // There's no register to read the regulator mode back from; check that the radio accepted
+                // the command, and is still in STDBY_RC.
+                let (op_mode, cmd_status) = self.get_status()?;
+                if op_mode != OperatingModeRead::StbyRc
+                    || cmd_status == CommandStatus::FailureToExecuteCommand
+                {
+                    return Err(RadioError::Status((op_mode, cmd_status)));
+                }
+
+                self.interface
+                    .write_op_word(OpCode::SetAutoFs, auto_fs as u8)?;
 
                 // todo: A/R. There's a subltety to it (See note below table 14-54)
-                // result.set_sync_word(network)?;
+                // self.set_sync_word(network)?;
             }
         }
 
-        Ok(result)
+        Ok(())
     }
 
     /// (6x) DS, section 14.2. Frequency is set here and in receive initiation, for use with frequency hopping.
     /// (8x) DS, section 14.4.22.
     pub fn send_payload(&mut self, payload: &[u8], rf_freq: u32) -> Result<(), RadioError> {
-        let payload_len = payload.len();
+        self.send(TxPayload::Slice(payload), rf_freq)
+    }
 
-        let op_code = match self.config {
-            RadioConfig::R6x(_) => OpCode::WriteBuffer as u8,
-            RadioConfig::R8x(_) => OpCode::WriteBuffer.val_8x(),
+    /// Send a payload on the stored Tx frequency: `tx_freq` if set, or `rf_freq` otherwise. For frequency-division
+    /// duplex links; eg a gateway receiving uplinks and transmitting downlinks on different channels.
+    pub fn send_payload_fdd(&mut self, payload: &[u8]) -> Result<(), RadioError> {
+        let rf_freq = match &self.config {
+            RadioConfig::R6x(c) => c.tx_freq.unwrap_or(c.rf_freq),
+            RadioConfig::R8x(c) => c.tx_freq.unwrap_or(c.rf_freq),
         };
+        self.send(TxPayload::Slice(payload), rf_freq)
+    }
 
-        let offset = 0;
-
-        self.interface.write_buf[0] = op_code;
-        self.interface.write_buf[1] = offset;
-        for (i, word) in payload.iter().enumerate() {
-            self.interface.write_buf[i + 2] = *word;
+    /// Send a payload from a caller-provided buffer, whose first 2 bytes are reserved; the payload starts
+    /// at index 2. We write the WriteBuffer opcode and offset into the reserved bytes, then send the whole
+    /// buffer as a single contiguous SPI write. This suits payloads staged in DMA-capable memory, since the
+    /// `SpiDevice` can transfer it in one operation. Otherwise, behaves like `send_payload`.
+    pub fn send_staged(&mut self, buf: &mut [u8], rf_freq: u32) -> Result<(), RadioError> {
+        if buf.len() < 2 {
+            return Err(RadioError::PayloadSize(buf.len()));
         }
+        self.send(TxPayload::Staged(buf), rf_freq)
+    }
 
-        // todo: This duplicated buffer prevents borrowed errors. Remove when we change to DMA.
-        let mut write_buf = [0; RADIO_BUF_SIZE];
-        write_buf[..payload_len + 2].copy_from_slice(&self.interface.write_buf[..payload_len + 2]);
+    /// Send a payload prefixed with a type (or length) byte, without concatenating them yourself. The combined
+    /// length must fit in the radio's buffer; otherwise, returns `RadioError::PayloadSize`. Otherwise, behaves
+    /// like `send_payload`.
+    pub fn send_framed(
+        &mut self,
+        type_byte: u8,
+        payload: &[u8],
+        rf_freq: u32,
+    ) -> Result<(), RadioError> {
+        let framed_len = payload.len() + 1;
+        check_tx_len(framed_len)?;
+
+        // 2 bytes are reserved for the opcode and offset; see `send_staged`.
+        let mut buf = [0; RADIO_BUF_SIZE + 2];
+        buf[2] = type_byte;
+        buf[3..3 + payload.len()].copy_from_slice(payload);
+
+        self.send(TxPayload::Staged(&mut buf[..2 + framed_len]), rf_freq)
+    }
 
-        if payload_len > RADIO_BUF_SIZE {
-            return Err(RadioError::PayloadSize(payload_len));
+    /// Write the payload to the radio's data buffer.
+    fn write_tx_payload(&mut self, payload: &mut TxPayload, offset: u8) -> Result<(), RadioError> {
+        match payload {
+            TxPayload::Slice(p) => self.interface.write_buffer(offset, p),
+            TxPayload::Staged(buf) => {
+                buf[0] = match self.config {
+                    RadioConfig::R6x(_) => OpCode::WriteBuffer as u8,
+                    RadioConfig::R8x(_) => OpCode::WriteBuffer.val_8x()?,
+                };
+                buf[1] = offset;
+                self.interface.write(buf)
+            }
         }
+    }
+
+    fn send(&mut self, mut payload: TxPayload, rf_freq: u32) -> Result<(), RadioError> {
+        let payload_len = payload.len();
+        check_tx_len(payload_len)?;
+
+        let offset = 0;
 
         // Separate to prevent borrow errors.
         match &mut self.config {
             RadioConfig::R6x(ref mut config) => {
                 config.rf_freq = rf_freq;
                 config.packet_params.payload_len = payload_len as u8;
+                config.packet_params_gfsk.payload_len = payload_len as u8;
             }
             RadioConfig::R8x(ref mut config) => {
                 config.rf_freq = rf_freq;
@@ -542,6 +1164,9 @@ impl Radio {
                 match &mut config.packet_params {
                     PacketParams::Lora(p) => p.payload_len = payload_len as u8,
                     PacketParams::Flrc(p) => p.payload_len = payload_len as u8,
+                    PacketParams::Gfsk(p) => p.payload_len = payload_len as u8,
+                    // The payload length is part of the BLE PDU header.
+                    PacketParams::Ble(_) => (),
                 }
             }
         }
@@ -550,15 +1175,12 @@ impl Radio {
         self.set_op_mode(OperatingMode::StbyRc)?;
 
         if !self.interface.r8x {
-            self.mod_quality_workaround();
+            self.mod_quality_workaround()?;
         }
 
         self.set_rf_freq()?;
 
-        let tx_addr = 0;
-        let rx_addr = 0;
-        self.interface
-            .write(&[OpCode::SetBufferBaseAddress as u8, tx_addr, rx_addr])?;
+        self.write_buffer_base()?;
 
         self.set_packet_params()?;
 
@@ -593,16 +1215,7 @@ impl Radio {
                 // self.interface
                 //     .write_with_payload(payload, offset)?;
 
-                // todo: Having trouble with SPI DMA writes (but reads work) Skipping for now; blocking.
-                {
-                    // let mut write_buf = [0; RADIO_BUF_SIZE];
-                    // write_buf[0] = OpCode::WriteBuffer as u8;
-                    // write_buf[1] = offset;
-                    // for (i, word) in payload.iter().enumerate() {
-                    //     write_buf[i + 2] = *word;
-                    // }
-                    self.interface.write(&write_buf[..2 + payload_len])?;
-                }
+                self.write_tx_payload(&mut payload, offset)?;
 
                 // 8. Define the modulation parameter according to the chosen protocol with the command SetModulationParams(...)1
                 // (set on init)
@@ -635,6 +1248,9 @@ impl Radio {
             RadioConfig::R8x(_config) => {
                 // self.set_rf_freq()?;
 
+                // Packet params, including the updated payload length, are re-sent above, ahead of this match;
+                // variable-length packets transmit with the wrong length otherwise.
+
                 // 1. Define the output power and ramp time by sending the command:
                 // (Set on init)
 
@@ -643,22 +1259,11 @@ impl Radio {
                 // where *data is a pointer to the payload and offset is the address at which the first byte of the payload will be located in the
                 // buffer. Offset will correspond to txBaseAddress in normal operation.
 
-                // todo: DRY with 6x.
-                let offset = 0;
                 // todo: Put back when reading.
                 // self.interface
                 //     .write_with_payload(payload, offset)?;
 
-                // todo: Having trouble with SPI DMA writes (but reads work) Skipping for now; blocking.
-                {
-                    // let mut write_buf = [0; RADIO_BUF_SIZE];
-                    // write_buf[0] = OpCode::WriteBuffer as u8;
-                    // write_buf[1] = offset;
-                    // for (i, word) in payload.iter().enumerate() {
-                    //     write_buf[i + 2] = *word;
-                    // }
-                    self.interface.write(&write_buf[..2 + payload_len])?;
-                }
+                self.write_tx_payload(&mut payload, offset)?;
 
                 // 3. Configure the DIOs and Interrupt sources (IRQs) by sending the command:
                 // SetDioIrqParams(irqMask,dio1Mask,dio2Mask,dio3Mask)
@@ -688,6 +1293,100 @@ impl Radio {
         Ok(())
     }
 
+    /// LoRa only. Send a payload, as with `send_payload`, but with CRC and header type overridden for this
+    /// packet; eg to interleave CRC-free beacons with CRC-protected data frames. The configured packet
+    /// params are restored afterwards, and take effect on the next transmission or reception.
+    pub fn send_payload_override(
+        &mut self,
+        payload: &[u8],
+        rf_freq: u32,
+        crc_enabled: CrcEnabled,
+        header_type: LoraHeaderType,
+    ) -> Result<(), RadioError> {
+        let params = match &mut self.config {
+            RadioConfig::R6x(c) if c.packet_type == PacketType6x::Lora => &mut c.packet_params,
+            RadioConfig::R8x(c) if c.packet_type == PacketType8x::Lora => {
+                match &mut c.packet_params {
+                    PacketParams::Lora(p) => p,
+                    _ => return Err(RadioError::ParamMismatch),
+                }
+            }
+            _ => return Err(RadioError::Config),
+        };
+
+        let orig = (params.crc_enabled, params.header_type);
+        params.crc_enabled = crc_enabled;
+        params.header_type = header_type;
+
+        let result = self.send_payload(payload, rf_freq);
+
+        let params = match &mut self.config {
+            RadioConfig::R6x(c) => &mut c.packet_params,
+            RadioConfig::R8x(c) => match &mut c.packet_params {
+                PacketParams::Lora(p) => p,
+                _ => return result,
+            },
+        };
+        (params.crc_enabled, params.header_type) = orig;
+
+        result
+    }
+
+    /// Send a payload, and block until transmission is complete; then run `cleanup_tx`. This is simpler
+    /// than the interrupt-driven approach (`send_payload`, then `cleanup_tx` from a DIO ISR), but blocks
+    /// the MCU for the entire time-on-air; use the ISR approach for anything time-sensitive.
+    pub fn transmit_blocking(&mut self, payload: &[u8], rf_freq: u32) -> Result<(), RadioError> {
+        self.send_payload(payload, rf_freq)?;
+
+        let r8x = self.interface.r8x;
+        let (tx_done, timeout) = (Irq::TxDone.mask(r8x), Irq::Timeout.mask(r8x));
+
+        let mut i = 0;
+        loop {
+            let irq_status = self.read_irq_word()?;
+
+            if irq_status & timeout != 0 {
+                self.clear_irq(&[Irq::TxDone, Irq::Timeout])?;
+                return Err(RadioError::Timeout);
+            }
+            if irq_status & tx_done != 0 {
+                break;
+            }
+
+            i += 1;
+            if i >= MAX_ITERS {
+                println!("Exceeded max iters waiting on Tx done.");
+                return Err(RadioError::Timeout);
+            }
+        }
+
+        self.cleanup_tx()
+    }
+
+    /// Send a beacon without confirming delivery: Clear any pending IRQs, then send as with `send_payload`,
+    /// using a fixed Tx timeout of 500ms, and return immediately after SetTx. This doesn't wait
+    /// for, or check TxDone, so minimizes CPU time per beacon; the radio returns to standby on its own. Don't
+    /// use this where you need to know the transmission succeeded.
+    pub fn transmit_beacon(&mut self, payload: &[u8], rf_freq: u32) -> Result<(), RadioError> {
+        self.clear_all_irq()?;
+
+        let tx_timeout = match &mut self.config {
+            RadioConfig::R6x(c) => &mut c.tx_timeout,
+            RadioConfig::R8x(c) => &mut c.tx_timeout,
+        };
+        let orig_timeout = *tx_timeout;
+        *tx_timeout = BEACON_TX_TIMEOUT_MS;
+
+        let result = self.send_payload(payload, rf_freq);
+
+        match &mut self.config {
+            RadioConfig::R6x(c) => c.tx_timeout = orig_timeout,
+            RadioConfig::R8x(c) => c.tx_timeout = orig_timeout,
+        }
+
+        result
+    }
+
     /// Run these from the SPI Tx complete ISR. This initiates transmission; run this once the
     /// payload write to the radio's buffer is complete.
     pub fn start_transmission(&mut self) -> Result<(), RadioError> {
@@ -703,28 +1402,186 @@ impl Radio {
         Ok(())
     }
 
-    /// (6x) Set the radio into receive mode. DS, section 14.3.
-    /// (8x) 14.4.3
-    /// todo: COnsider also using the SetDutyCycle sniff mode.
-    pub fn receive(&mut self, max_payload_len: u8, rf_freq: u32) -> Result<(), RadioError> {
-        // Config access is separate to prevent borrow errors.
-        match &mut self.config {
-            RadioConfig::R6x(ref mut config) => {
-                config.rf_freq = rf_freq;
-                config.packet_params.payload_len = max_payload_len;
-            }
-            RadioConfig::R8x(ref mut config) => {
-                config.rf_freq = rf_freq;
+    /// Start LoRa channel activity detection, using parameters set with `set_cad_params`. `CadDone` and
+    /// `CadDetected` are mapped to DIO1. If the exit mode is `Rx`, `RxDone` and `Timeout` are mapped to
+    /// DIO3, as with `receive`. 6x: DS, section 13.1.8. 8x: section 11.6.9.
+    pub fn start_cad(&mut self) -> Result<(), RadioError> {
+        self.set_irq(
+            &[Irq::CadDone, Irq::CadDetected],
+            &[Irq::RxDone, Irq::Timeout],
+        )?;
 
-                match &mut config.packet_params {
-                    PacketParams::Lora(p) => p.payload_len = max_payload_len,
-                    PacketParams::Flrc(p) => p.payload_len = max_payload_len,
-                }
-            }
+        self.interface.write(&[OpCode::SetCAD as u8]) // Same opcode on 6x and 8x.
+    }
+
+    /// LoRa only. Run channel activity detection, and only if activity is detected, receive with
+    /// `receive_blocking`, using the configured `rx_timeout`. Returns `None` immediately if the channel is
+    /// clear, so the MCU can go back to sleep. This uses much less energy than waiting in Rx. The CAD
+    /// symbol count and thresholds are set from the spreading factor; see `CadParams::for_sf`. Blocking.
+    pub fn cad_then_receive(
+        &mut self,
+        buf: &mut [u8],
+        rf_freq: u32,
+    ) -> Result<Option<usize>, RadioError> {
+        let rx_timeout = self.setup_cad(rf_freq)?;
+
+        if !self.cad_blocking()? {
+            return Ok(None);
         }
 
-        match &self.config {
-            RadioConfig::R6x(config) => {
+        self.receive_blocking(buf, rx_timeout, rf_freq)
+    }
+
+    /// LoRa only. Run channel activity detection `trials` times, and return the fraction (0.0 - 1.0) of trials
+    /// that detected activity. A lightweight occupancy metric; unlike RSSI, this detects LoRa signals below
+    /// the noise floor. Uses the same CAD params as `cad_then_receive`. Blocking.
+    pub fn channel_activity_fraction(
+        &mut self,
+        rf_freq: u32,
+        trials: u8,
+    ) -> Result<f32, RadioError> {
+        if trials == 0 {
+            return Err(RadioError::Config);
+        }
+
+        self.setup_cad(rf_freq)?;
+
+        let mut detected = 0;
+        for _ in 0..trials {
+            if self.cad_blocking()? {
+                detected += 1;
+            }
+        }
+
+        Ok(detected as f32 / trials as f32)
+    }
+
+    /// Enter standby, and set the frequency and CAD params for the configured spreading factor. Returns the
+    /// configured Rx timeout.
+    fn setup_cad(&mut self, rf_freq: u32) -> Result<f32, RadioError> {
+        let (sf, rx_timeout) = match &mut self.config {
+            RadioConfig::R6x(c) if c.packet_type == PacketType6x::Lora => {
+                c.rf_freq = rf_freq;
+                (c.modulation_params.spreading_factor, c.rx_timeout)
+            }
+            RadioConfig::R8x(c) if c.packet_type == PacketType8x::Lora => {
+                c.rf_freq = rf_freq;
+                match &c.modulation_params {
+                    ModulationParams8x::Lora(p) => (p.spreading_factor, c.rx_timeout),
+                    _ => return Err(RadioError::ParamMismatch),
+                }
+            }
+            _ => return Err(RadioError::Config),
+        };
+
+        self.set_op_mode(OperatingMode::StbyRc)?;
+        self.set_rf_freq()?;
+        self.set_cad_params(&CadParams::for_sf(sf))?;
+
+        Ok(rx_timeout)
+    }
+
+    /// Run CAD, block until it's done, and clear its IRQs. Returns true if activity was detected.
+    fn cad_blocking(&mut self) -> Result<bool, RadioError> {
+        self.start_cad()?;
+
+        let r8x = self.interface.r8x;
+        let (cad_done, cad_detected) = (Irq::CadDone.mask(r8x), Irq::CadDetected.mask(r8x));
+
+        let mut i = 0;
+        let irq_status = loop {
+            let irq_status = self.read_irq_word()?;
+            if irq_status & cad_done != 0 {
+                break irq_status;
+            }
+
+            i += 1;
+            if i >= MAX_ITERS {
+                println!("Exceeded max iters waiting on CAD done.");
+                return Err(RadioError::Timeout);
+            }
+        };
+
+        self.clear_irq(&[Irq::CadDone, Irq::CadDetected])?;
+
+        Ok(irq_status & cad_detected != 0)
+    }
+
+    /// Enter receive mode on the stored Rx frequency: `rx_freq` if set, or `rf_freq` otherwise. See
+    /// `send_payload_fdd`.
+    pub fn receive_fdd(&mut self, max_payload_len: u8) -> Result<(), RadioError> {
+        let rf_freq = match &self.config {
+            RadioConfig::R6x(c) => c.rx_freq.unwrap_or(c.rf_freq),
+            RadioConfig::R8x(c) => c.rx_freq.unwrap_or(c.rf_freq),
+        };
+        self.receive(max_payload_len, rf_freq)
+    }
+
+    /// Re-enter receive mode using the settings from the last `receive` call, by only issuing SetRx. This skips
+    /// the standby transition, and re-sending frequency, buffer, packet, and IRQ params, to shorten turnaround in
+    /// polling receivers. Only use this if nothing has changed since the last `receive` (no transmissions, config
+    /// changes, or sleep), and after `cleanup_rx`, so the previous reception's IRQs are cleared.
+    pub fn rearm_rx(&mut self) -> Result<(), RadioError> {
+        let timeout = match &self.config {
+            RadioConfig::R6x(c) => c.rx_timeout,
+            RadioConfig::R8x(c) => c.rx_timeout,
+        };
+
+        self.set_op_mode(OperatingMode::Rx(timeout))
+    }
+
+    /// (6x) Set the radio into receive mode. DS, section 14.3.
+    /// (8x) 14.4.3
+    /// On 6x LoRa, this applies the IQ polarity errata fix for `invert_iq_rx`, via `set_packet_params_rx`.
+    pub fn receive(&mut self, max_payload_len: u8, rf_freq: u32) -> Result<(), RadioError> {
+        self.start_rx(max_payload_len, rf_freq, None)
+    }
+
+    /// As `receive`, but using the radio's Rx duty cycle (sniff) mode: the radio alternates between listening
+    /// for `rx_ms`, and sleeping for `sleep_ms`, until a packet is detected; this saves power when packets
+    /// are infrequent. Make `rx_ms` long enough to detect a preamble, and the transmitter's preamble longer
+    /// than the sleep period. 6x: DS, section 13.1.7. 8x: section 11.6.6.
+    pub fn receive_duty_cycle(
+        &mut self,
+        max_payload_len: u8,
+        rf_freq: u32,
+        rx_ms: f32,
+        sleep_ms: f32,
+    ) -> Result<(), RadioError> {
+        self.start_rx(max_payload_len, rf_freq, Some((rx_ms, sleep_ms)))
+    }
+
+    /// Configure for Rx, then enter it; with a duty cycle of (Rx ms, sleep ms) if set.
+    fn start_rx(
+        &mut self,
+        max_payload_len: u8,
+        rf_freq: u32,
+        duty_cycle: Option<(f32, f32)>,
+    ) -> Result<(), RadioError> {
+        // Config access is separate to prevent borrow errors.
+        match &mut self.config {
+            RadioConfig::R6x(ref mut config) => {
+                config.rf_freq = rf_freq;
+                config.packet_params.payload_len = max_payload_len;
+                config.packet_params_gfsk.payload_len = max_payload_len;
+            }
+            RadioConfig::R8x(ref mut config) => {
+                config.rf_freq = rf_freq;
+
+                match &mut config.packet_params {
+                    PacketParams::Lora(p) => p.payload_len = max_payload_len,
+                    PacketParams::Flrc(p) => p.payload_len = max_payload_len,
+                    PacketParams::Gfsk(p) => p.payload_len = max_payload_len,
+                    PacketParams::Ble(_) => (),
+                }
+            }
+        }
+
+        // Packets are written from the Rx base address again; see `drain_rx`.
+        self.interface.rx_read_ptr = Some(self.rx_base);
+
+        match &self.config {
+            RadioConfig::R6x(config) => {
                 let timeout = config.rx_timeout; // prevents borrow errors.
 
                 // 1. If not in STDBY_RC mode, then set the circuit in this mode with the command SetStandby()
@@ -740,19 +1597,16 @@ impl Radio {
 
                 // 4. Define where the data will be stored inside the data buffer in Rx with the command SetBufferBaseAddress(...)
                 // (Note: We may have to set this here, since I believe this setting auto-increments.)
-                let tx_addr = 0;
-                let rx_addr = 0;
-                self.interface
-                    .write(&[OpCode::SetBufferBaseAddress as u8, tx_addr, rx_addr])?;
+                self.write_buffer_base()?;
 
                 // 5. Define the modulation parameter according to the chosen protocol with the command SetModulationParams(...)1
                 // (Set on init)
                 // self.set_mod_params_sx126x()?;
 
                 // 6. Define the frame format to be used with the command SetPacketParams(...)
-                // We must set this, as it may have been changed during a transmission to payload length.
-                // Set on init
-                // self.set_packet_params()?;
+                // We must set this, as it may have been changed during a transmission to payload length,
+                // and the IQ polarity may differ between Tx and Rx.
+                self.set_packet_params_rx()?;
 
                 // 7. Configure DIO and irq: use the command SetDioIrqParams(...) to select the IRQ RxDone and map this IRQ to a DIO (DIO1
                 // or DIO2 or DIO3), set IRQ Timeout as well.
@@ -765,7 +1619,10 @@ impl Radio {
                 // todo receiving multiple packets. (0xffff on 8x)
 
                 // 9. Set the circuit in reception mode: use the command SetRx(). Set the parameter to enable timeout or continuous mode
-                self.set_op_mode(OperatingMode::Rx(timeout))?;
+                match duty_cycle {
+                    Some((rx_ms, sleep_ms)) => self.set_rx_duty_cycle(rx_ms, sleep_ms)?,
+                    None => self.set_op_mode(OperatingMode::Rx(timeout))?,
+                }
 
                 // 10. Wait for IRQ RxDone2 or Timeout: the chip will stay in Rx and look for a new packet if the continuous mode is selected
                 // otherwise it will goes to STDBY_RC mode.
@@ -773,20 +1630,37 @@ impl Radio {
             }
             RadioConfig::R8x(config) => {
                 let timeout = config.rx_timeout; // prevents borrow errors.
+                let flrc = config.packet_type == PacketType8x::Flrc;
+                let implicit_len = match &config.packet_params {
+                    PacketParams::Lora(p) if p.header_type == LoraHeaderType::FixedLength => {
+                        Some(p.payload_len)
+                    }
+                    _ => None,
+                };
 
                 self.set_op_mode(OperatingMode::StbyRc)?;
 
-                let tx_addr = 0;
-                let rx_addr = 0;
-                self.interface
-                    .write(&[OpCode::SetBufferBaseAddress.val_8x(), tx_addr, rx_addr])?;
+                self.write_buffer_base()?;
 
                 self.set_rf_freq()?;
+                // Payload length and IQ polarity may differ from Tx.
+                self.set_packet_params_rx()?;
+
+                if let Some(payload_len) = implicit_len {
+                    self.set_lora_header_mode(LoraHeaderType::FixedLength, payload_len)?;
+                }
 
                 // 1. Configure the DIOs and Interrupt sources (IRQs) by using command:
                 // SetDioIrqParams(irqMask,dio1Mask,dio2Mask,dio3Mask)
 
-                self.set_irq(&[], &[Irq::RxDone, Irq::Timeout])?; // DIO3.
+                // FLRC: Enable the sync word and CRC error IRQs, so `cleanup_rx` can read them. They're
+                // not mapped to a DIO.
+                let irq_only: &[Irq] = if flrc {
+                    &[Irq::SyncWordError, Irq::CrcErr]
+                } else {
+                    &[]
+                };
+                self.set_irq_params(irq_only, &[], &[], &[Irq::RxDone, Irq::Timeout])?; // DIO3.
 
                 // 2.Once configured, set the transceiver in receiver mode to start reception using command:
                 // SetRx(periodBase, periodBaseCount[15:8], periodBaseCount[7:0])
@@ -800,7 +1674,10 @@ impl Radio {
                 // to STDBY_RC Mode on timer end-of-count or when a packet has been received. As soon as a packet is detected, the
                 // timer is automatically disabled to allow complete reception of the packet.
 
-                self.set_op_mode(OperatingMode::Rx(timeout))?;
+                match duty_cycle {
+                    Some((rx_ms, sleep_ms)) => self.set_rx_duty_cycle(rx_ms, sleep_ms)?,
+                    None => self.set_op_mode(OperatingMode::Rx(timeout))?,
+                }
 
                 // 3. In typical cases, use a timeout and wait for IRQ RxDone or RxTxTimeout.
                 // If IRQ RxDone is asserted, the transceiver goes to STDBY_RC mode if single mode is used (timeout set to a value different
@@ -851,17 +1728,29 @@ impl Radio {
         Ok(())
     }
     /// Run these after transmission is complete, eg in an ISR. Clears the IRQ, and reports errors.
+    /// The radio must be in the configured fallback mode. 6x: The command status must be Tx done.
+    /// 8x: It may also be success, since the status reflects the preceding ClearIrqStatus command.
     pub fn cleanup_tx(&mut self) -> Result<(), RadioError> {
         self.clear_irq(&[Irq::TxDone, Irq::Timeout])?;
 
-        let status = self.get_status()?;
-        if status.0 != OperatingModeRead::StbyRc || status.1 != CommandStatus::CommandTxDone {
+        let fallback = match &self.config {
+            RadioConfig::R6x(c) => c.fallback_mode,
+            RadioConfig::R8x(c) => c.fallback_mode,
+        };
+
+        let status_byte = self.get_status_byte()?;
+        let status = self.decode_status(status_byte)?;
+
+        if !tx_status_ok(status, fallback, self.interface.r8x) {
             // Note: For Rx cleanup, we allow timeouts, because no message may be received. For Tx,
             // we don't, as this indicates a problem.
-            println!(
-                "\nProblem with Tx status post-write. Operating mode: {} Command status: {}",
-                status.0, status.1
-            );
+            match self.status_callback {
+                Some(cb) => cb(status_byte),
+                None => println!(
+                    "\nProblem with Tx status post-write. Operating mode: {} Command status: {}",
+                    status.0, status.1
+                ),
+            }
             return Err(RadioError::Status((status.0, status.1)));
         }
 
@@ -877,19 +1766,32 @@ impl Radio {
         Ok(())
     }
 
+    /// A lightweight alternative to `cleanup_tx`, for back-to-back transmissions: This only clears the
+    /// Tx IRQs. It skips reading status and device errors, so it's faster, but won't report problems.
+    /// Use `cleanup_tx` when diagnostics matter.
+    pub fn tx_done_fast(&mut self) -> Result<(), RadioError> {
+        self.clear_irq(&[Irq::TxDone, Irq::Timeout])
+    }
+
     /// Run these after reception is complete, eg in an ISR. Returns buffer status (payload size and start index),
     /// and command status (Data available, timeout etc). Note: If we didn't receive a message, run `clear_irq` instead of this.
     pub fn cleanup_rx(&mut self) -> Result<(RxBufferStatus, CommandStatus), RadioError> {
-        let (op_mode, cmd_status) = self.get_status()?;
-
-        if op_mode != OperatingModeRead::StbyRc
-            || (cmd_status != CommandStatus::DataAvailable
-                && cmd_status != CommandStatus::CommandTimeout)
-        {
-            println!(
-                "\nProblem with Rx status post-read. Operating mode: {} Command status: {}",
-                op_mode, cmd_status
-            );
+        let fallback = match &self.config {
+            RadioConfig::R6x(c) => c.fallback_mode,
+            RadioConfig::R8x(c) => c.fallback_mode,
+        };
+
+        let status_byte = self.get_status_byte()?;
+        let (op_mode, cmd_status) = self.decode_status(status_byte)?;
+
+        if !rx_status_ok((op_mode, cmd_status), fallback) {
+            match self.status_callback {
+                Some(cb) => cb(status_byte),
+                None => println!(
+                    "\nProblem with Rx status post-read. Operating mode: {} Command status: {}",
+                    op_mode, cmd_status
+                ),
+            }
             // This executes if we didn't receive a message.
             self.clear_irq(&[Irq::RxDone, Irq::Timeout])?;
             return Err(RadioError::Status((op_mode, cmd_status)));
@@ -900,21 +1802,55 @@ impl Radio {
         // The IRQ RxDone means that a packet has been received but the CRC could be wrong: the user must check the CRC before
         // validating the packet.
         if cmd_status == CommandStatus::DataAvailable {
-            let op_code = match self.config {
-                RadioConfig::R6x(_) => OpCode::GetIrqStatus as u8,
-                RadioConfig::R8x(_) => OpCode::GetIrqStatus.val_8x(),
+            let irq_status = self.read_irq_word()?;
+
+            let flrc = matches!(
+                &self.config,
+                RadioConfig::R8x(c) if c.packet_type == PacketType8x::Flrc
+            );
+            if let Some((e, irq)) = rx_irq_error(
+                irq_status,
+                self.interface.r8x,
+                flrc,
+                self.config.crc_enabled(),
+            ) {
+                println!("Irq error post-read: {} IRQ word: {}", e, irq_status);
+                // Clear the IRQs even if we are returning early.
+                self.clear_irq(&[Irq::RxDone, Irq::Timeout, irq])?;
+                return Err(e);
+            }
+
+            let (min_snr_db, min_rssi_dbm) = match &self.config {
+                RadioConfig::R6x(c) if c.packet_type == PacketType6x::Lora => {
+                    (c.min_snr_db, c.min_rssi_dbm)
+                }
+                RadioConfig::R8x(c) if c.packet_type == PacketType8x::Lora => {
+                    (c.min_snr_db, c.min_rssi_dbm)
+                }
+                _ => (None, None),
             };
 
-            let mut irq_status_buf = [op_code, 0, 0, 0];
+            if min_snr_db.is_some() || min_rssi_dbm.is_some() {
+                let packet_status = self.get_packet_status()?;
 
-            self.interface.read(&mut irq_status_buf)?;
+                if let Some(min_snr) = min_snr_db {
+                    let snr = packet_status.snr_db(self.interface.r8x);
+                    if snr < min_snr {
+                        println!("Rx SNR below floor: {}", snr);
+                        self.clear_irq(&[Irq::RxDone, Irq::Timeout])?;
+                        return Err(RadioError::LowSnr);
+                    }
+                }
 
-            let irq_status = u16::from_be_bytes([irq_status_buf[2], irq_status_buf[3]]);
-            if irq_status & (0b11 << 5) != 0 {
-                // Mask for header CRC error or wrong CRC received.
-                println!("Irq CRC error post-read: {}", irq_status); // ensure bit 6 isn't set to validate CRC.
-                self.clear_irq(&[Irq::RxDone, Irq::Timeout])?; // Clear the IRQ even if we are returning early.
-                return Err(RadioError::Crc);
+                if let Some(min_rssi) = min_rssi_dbm {
+                    // Actual signal power is –RssiPkt/2.
+                    let rssi = -(packet_status.rssi as f32) / 2.;
+                    if rssi < min_rssi {
+                        println!("Rx RSSI below floor: {}", rssi);
+                        self.clear_irq(&[Irq::RxDone, Irq::Timeout])?;
+                        return Err(RadioError::LowRssi);
+                    }
+                }
             }
         }
 
@@ -932,8 +1868,13 @@ impl Radio {
         self.interface.rx_payload_len = buf_status.payload_len;
         self.interface.rx_payload_start = buf_status.rx_start_buf_pointer;
 
-        if let RadioConfig::R6x(_) = self.config {
-            self.implicit_header_to_workaround()?; // See eratta, section 15.3.
+        if let RadioConfig::R6x(c) = &self.config {
+            // See eratta, section 15.3. This only applies to LoRa in implicit header mode.
+            if c.packet_type == PacketType6x::Lora
+                && c.packet_params.header_type == LoraHeaderType::FixedLength
+            {
+                self.implicit_header_to_workaround()?;
+            }
 
             // No device errors opcode on 8x.
             let device_errors = self.get_device_errors()?;
@@ -952,37 +1893,361 @@ impl Radio {
 
             // todo TS. It seems DMA may be at the core of your demons.
 
-            // todo: This duplicate buffer prevents a borrow mut erroro for now. Change once we use DMA.
-            let mut buf = [0; RADIO_BUF_SIZE];
-            //
-            let op_code = match self.config {
-                RadioConfig::R6x(_) => OpCode::ReadBuffer as u8,
-                RadioConfig::R8x(_) => OpCode::ReadBuffer.val_8x(),
+            check_rx_len(buf_status.payload_len as usize, self.max_payload_len())?;
+
+            // This reads only the payload into `read_buf`. Don't continue on failure; the buffer contents
+            // would be garbage.
+            self.interface.read_buffer(
+                buf_status.rx_start_buf_pointer,
+                buf_status.payload_len as usize,
+            )?;
+        }
+
+        // (Process the payload in the SPI Rx complete ISR)
+
+        Ok((buf_status, cmd_status))
+    }
+
+    /// For continuous receive. Read every packet received since the last call (or since Rx started), oldest
+    /// first, into `out`, one packet per slot. Returns the number of packets read. Each is
+    /// `interface.rx_payload_len` bytes long. Use this in place of `cleanup_rx` when several packets may
+    /// arrive before the MCU services them. This clears the RxDone IRQ.
+    ///
+    /// The radio writes successive packets contiguously into its 256-byte circular buffer, but only reports
+    /// the start and length of the last one. So, earlier packets can only be separated if they're all the same
+    /// length as the last; eg with a fixed-length header. Otherwise, only the last packet is read. If more
+    /// packets are pending than slots in `out`, the remaining ones are left for the next call.
+    ///
+    /// If unread packets exceed 256 bytes, the buffer wraps, and the oldest are overwritten. The radio
+    /// doesn't report this, so call this often enough to stay ahead of it. Exactly 256 unread bytes is
+    /// handled, using the RxDone flag to tell a full buffer from an empty one. CRC status is only available
+    /// for the last packet.
+    ///
+    /// Returns `RadioError::PayloadSize` without reading anything if a slot that would be filled is shorter
+    /// than the packet length; those packets remain available to the next call.
+    pub fn drain_rx(&mut self, out: &mut [&mut [u8]]) -> Result<usize, RadioError> {
+        let rx_done = self.read_irq_word()? & Irq::RxDone.mask(self.interface.r8x) != 0;
+        self.clear_irq(&[Irq::RxDone])?;
+
+        let buf_status = self.get_rx_buffer_status()?;
+        let last_start = buf_status.rx_start_buf_pointer;
+        let read_ptr = self.interface.rx_read_ptr.unwrap_or(last_start);
+
+        let (mut ptr, num_pending) =
+            rx_pending(read_ptr, last_start, buf_status.payload_len, rx_done);
+        let len = buf_status.payload_len as usize;
+
+        let num_read = num_pending.min(out.len());
+        // Check every slot first, so we don't advance past packets we can't return.
+        if out.iter().take(num_read).any(|slot| slot.len() < len) {
+            return Err(RadioError::PayloadSize(len));
+        }
+
+        for slot in out.iter_mut().take(num_read) {
+            self.interface.read_buffer(ptr, len)?;
+            slot[..len].copy_from_slice(&self.interface.read_buf[..len]);
+
+            ptr = ptr.wrapping_add(len as u8);
+            self.interface.rx_read_ptr = Some(ptr);
+        }
+
+        self.interface.rx_payload_len = buf_status.payload_len;
+        self.interface.rx_payload_start = last_start;
+
+        Ok(num_read)
+    }
+
+    /// The maximum payload length set in the packet params; by `receive`, or for transmissions, the payload
+    /// length. BLE payload length is part of the PDU, so isn't limited here.
+    fn max_payload_len(&self) -> u8 {
+        match &self.config {
+            RadioConfig::R6x(c) => match c.packet_type {
+                PacketType6x::Gfsk => c.packet_params_gfsk.payload_len,
+                _ => c.packet_params.payload_len,
+            },
+            RadioConfig::R8x(c) => match &c.packet_params {
+                PacketParams::Lora(p) => p.payload_len,
+                PacketParams::Flrc(p) => p.payload_len,
+                PacketParams::Gfsk(p) => p.payload_len,
+                PacketParams::Ble(_) => u8::MAX,
+            },
+        }
+    }
+
+    /// The configured LoRa header type; `None` if the packet type isn't LoRa. Neither radio reports the
+    /// header mode of a received packet: the receiver must be configured for the same mode as the transmitter,
+    /// so this is inferred from config. In implicit mode, a packet sent with an explicit header won't be
+    /// received correctly, and vice versa.
+    pub fn lora_header_type(&self) -> Option<LoraHeaderType> {
+        match &self.config {
+            RadioConfig::R6x(c) => match c.packet_type {
+                PacketType6x::Lora => Some(c.packet_params.header_type),
+                _ => None,
+            },
+            RadioConfig::R8x(c) => match &c.packet_params {
+                PacketParams::Lora(p) => Some(p.header_type),
+                _ => None,
+            },
+        }
+    }
+
+    /// Put the radio in continuous receive mode, with no IRQs set, for reading instantaneous RSSI with
+    /// `get_rssi_inst`; eg for squelch, or channel activity checks. The radio remains in Rx until
+    /// commanded otherwise; eg with `set_op_mode(OperatingMode::StbyRc)`.
+    /// 6x: DS, section 13.1.5. 8x: Table 11-26.
+    pub fn enter_rssi_scan(&mut self, rf_freq: u32) -> Result<(), RadioError> {
+        match &mut self.config {
+            RadioConfig::R6x(c) => c.rf_freq = rf_freq,
+            RadioConfig::R8x(c) => c.rf_freq = rf_freq,
+        }
+
+        self.set_op_mode(OperatingMode::StbyRc)?;
+        self.set_rf_freq()?;
+
+        // A timeout of 0xff_ffff (6x) or 0xffff (8x) sets continuous mode.
+        let buf = match self.config {
+            RadioConfig::R6x(_) => [OpCode::SetRx as u8, 0xff, 0xff, 0xff],
+            RadioConfig::R8x(_) => [OpCode::SetRx.val_8x()?, 0, 0xff, 0xff],
+        };
+        self.interface.write(&buf)?;
+        self.ensure_locked()
+    }
+
+    /// Measure the channel's noise floor, in dBm: Enter continuous Rx, take `samples` instantaneous RSSI
+    /// readings, and return the lowest. Set a squelch threshold a few dB above this. Returns the radio to
+    /// standby when done. Blocking.
+    pub fn measure_noise_floor(&mut self, samples: u8, rf_freq: u32) -> Result<i8, RadioError> {
+        if samples == 0 {
+            return Err(RadioError::Config);
+        }
+
+        self.enter_rssi_scan(rf_freq)?;
+
+        let mut result = i8::MAX;
+        for _ in 0..samples {
+            // Spacing the readings makes them less correlated, and gives the RSSI time to settle.
+            self.interface.delay.delay_us(RSSI_SAMPLE_SPACING_US);
+            result = result.min(self.get_rssi_inst()?);
+        }
+
+        self.set_op_mode(OperatingMode::StbyRc)?;
+
+        Ok(result)
+    }
+
+    /// Sweep from `start_hz` to `stop_hz` (inclusive) in steps of `step_hz`, recording the peak
+    /// instantaneous RSSI (dBm) seen over `dwell_ms` at each step into `out`; eg for site surveys.
+    /// The step count is bounded by `out.len()`. Returns the number of steps recorded. Returns the radio
+    /// to standby, at its configured frequency, when done. Blocking.
+    pub fn occupancy_sweep(
+        &mut self,
+        start_hz: u32,
+        stop_hz: u32,
+        step_hz: u32,
+        dwell_ms: u32,
+        out: &mut [i8],
+    ) -> Result<usize, RadioError> {
+        if step_hz == 0 || stop_hz < start_hz {
+            return Err(RadioError::Config);
+        }
+
+        let rf_freq_orig = match &self.config {
+            RadioConfig::R6x(c) => c.rf_freq,
+            RadioConfig::R8x(c) => c.rf_freq,
+        };
+
+        let samples = (dwell_ms.saturating_mul(1_000) / RSSI_SAMPLE_SPACING_US).max(1);
+
+        let mut num_steps = 0;
+        let mut freq = start_hz;
+        while num_steps < out.len() && freq <= stop_hz {
+            // `enter_rssi_scan` goes through standby before retuning.
+            self.enter_rssi_scan(freq)?;
+
+            let mut peak = i8::MIN;
+            for _ in 0..samples {
+                self.interface.delay.delay_us(RSSI_SAMPLE_SPACING_US);
+                peak = peak.max(self.get_rssi_inst()?);
+            }
+
+            out[num_steps] = peak;
+            num_steps += 1;
+
+            freq = match freq.checked_add(step_hz) {
+                Some(f) => f,
+                None => break,
             };
+        }
+
+        self.set_op_mode(OperatingMode::StbyRc)?;
+
+        match &mut self.config {
+            RadioConfig::R6x(c) => c.rf_freq = rf_freq_orig,
+            RadioConfig::R8x(c) => c.rf_freq = rf_freq_orig,
+        }
+        self.set_rf_freq()?;
 
-            buf[0] = op_code;
-            buf[1] = buf_status.rx_start_buf_pointer;
-            buf[2] = 0;
+        Ok(num_steps)
+    }
 
-            let payload_len = buf_status.payload_len as usize;
-            let buf_len = payload_len + 3;
+    /// 6x only. Fill `buf` with random bytes from the radio's random number generator, which samples
+    /// wideband noise; eg for seeding an RNG on an MCU without a hardware TRNG. The registers are only
+    /// updated in Rx mode, so we enter continuous Rx at the configured frequency, and XOR several
+    /// successive reads into each output word. Returns the radio to standby when done. Blocking.
+    /// DS, Table 12-1; Semtech's reference driver, `SX126xGetRandom`.
+    pub fn fill_entropy(&mut self, buf: &mut [u8]) -> Result<(), RadioError> {
+        let rf_freq = match &self.config {
+            RadioConfig::R6x(c) => c.rf_freq,
+            RadioConfig::R8x(_) => return Err(RadioError::Config),
+        };
 
-            if self.interface.read(&mut buf[..buf_len]).is_err() {
-                println!("Error reading the buffer");
+        self.enter_rssi_scan(rf_freq)?;
+
+        let regs = [
+            Register6x::RandomNumGen0,
+            Register6x::RandomNumGen1,
+            Register6x::RandomNumGen2,
+            Register6x::RandomNumGen3,
+        ];
+
+        for chunk in buf.chunks_mut(4) {
+            let mut word = [0; 4];
+            for _ in 0..ENTROPY_MIX_READS {
+                self.interface.delay.delay_us(ENTROPY_SAMPLE_SPACING_US);
+                for (byte, reg) in word.iter_mut().zip(regs) {
+                    *byte ^= self.interface.read_reg_word(Register::Reg6x(reg))?;
+                }
             }
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+
+        self.set_op_mode(OperatingMode::StbyRc)
+    }
 
-            // Transfer data from our SPI read buffer to our internal buffer; this includes
-            // only the payload.
-            self.interface.read_buf[..payload_len].copy_from_slice(&buf[3..buf_len])
+    /// Run this after reception is complete, eg in an ISR. A simpler alternative to `cleanup_rx`: Returns
+    /// `None` if the receive window timed out without a packet, and the buffer status (payload size and
+    /// start index) if data was received. The payload is then available in `interface.rx_payload_from_buf()`.
+    pub fn receive_complete(&mut self) -> Result<Option<RxBufferStatus>, RadioError> {
+        let (buf_status, cmd_status) = self.cleanup_rx()?;
+
+        if cmd_status == CommandStatus::CommandTimeout {
+            Ok(None)
+        } else {
+            Ok(Some(buf_status))
         }
+    }
 
-        // (Process the payload in the SPI Rx complete ISR)
+    /// Enter receive mode, and block until a packet is received, or the radio's receive timeout elapses;
+    /// then run `cleanup_rx`, and copy the payload into `buf`. Returns the payload length, or `None` if
+    /// no packet arrived before `timeout_ms`. Like `transmit_blocking`, this blocks the MCU for the whole
+    /// receive window; use `receive` with a DIO ISR and `receive_complete` for non-blocking operation.
+    ///
+    /// `timeout_ms` must be positive; the radio treats 0 as single mode without a timeout, which would
+    /// block indefinitely. The configured `rx_timeout` is left unchanged.
+    pub fn receive_blocking(
+        &mut self,
+        buf: &mut [u8],
+        timeout_ms: f32,
+        rf_freq: u32,
+    ) -> Result<Option<usize>, RadioError> {
+        if timeout_ms <= 0. {
+            return Err(RadioError::Config);
+        }
 
-        Ok((buf_status, cmd_status))
+        let rx_timeout = match &mut self.config {
+            RadioConfig::R6x(c) => &mut c.rx_timeout,
+            RadioConfig::R8x(c) => &mut c.rx_timeout,
+        };
+        let orig_timeout = *rx_timeout;
+        *rx_timeout = timeout_ms;
+
+        let max_payload_len = buf.len().min(RADIO_BUF_SIZE - 1) as u8;
+        let result = self.receive(max_payload_len, rf_freq);
+
+        // The timeout is only used when entering Rx.
+        match &mut self.config {
+            RadioConfig::R6x(c) => c.rx_timeout = orig_timeout,
+            RadioConfig::R8x(c) => c.rx_timeout = orig_timeout,
+        }
+        result?;
+
+        let r8x = self.interface.r8x;
+        let (rx_done, timeout) = (Irq::RxDone.mask(r8x), Irq::Timeout.mask(r8x));
+
+        let mut i = 0;
+        loop {
+            let irq_status = self.read_irq_word()?;
+            if irq_status & (rx_done | timeout) != 0 {
+                break;
+            }
+
+            i += 1;
+            if i >= MAX_ITERS {
+                println!("Exceeded max iters waiting on Rx done.");
+                // Don't leave the radio listening.
+                self.set_op_mode(OperatingMode::StbyRc)?;
+                return Err(RadioError::Timeout);
+            }
+        }
+
+        let Some(buf_status) = self.receive_complete()? else {
+            return Ok(None);
+        };
+
+        let payload_len = buf_status.payload_len as usize;
+        if payload_len > buf.len() {
+            return Err(RadioError::PayloadSize(payload_len));
+        }
+
+        buf[..payload_len].copy_from_slice(&self.interface.read_buf[..payload_len]);
+
+        Ok(Some(payload_len))
+    }
+
+    /// Send a payload, then listen for an acknowledgement for up to `ack_timeout_ms`, retrying the send up
+    /// to `retries` times if none arrives. `is_ack` is run on each received payload, and decides if it's the
+    /// acknowledgement we're waiting for; eg by checking a message type and sequence number. Packets that fail
+    /// CRC or SNR checks are treated as no acknowledgement. Returns `RadioError::Timeout` if all attempts fail.
+    ///
+    /// This is blocking; see `transmit_blocking` and `receive_blocking`. Note that the radio is half-duplex:
+    /// the receiver must finish receiving, process the packet, and switch to Tx before it can send the
+    /// acknowledgement, and we must switch from Tx to Rx before it arrives. Set `ack_timeout_ms` to cover
+    /// both turnarounds and the ack's time-on-air, and keep the peer's response delay short.
+    pub fn send_with_ack<F>(
+        &mut self,
+        payload: &[u8],
+        rf_freq: u32,
+        ack_timeout_ms: f32,
+        retries: u8,
+        is_ack: F,
+    ) -> Result<(), RadioError>
+    where
+        F: Fn(&[u8]) -> bool,
+    {
+        let mut ack_buf = [0; RADIO_BUF_SIZE];
+
+        for _ in 0..=retries {
+            self.transmit_blocking(payload, rf_freq)?;
+
+            match self.receive_blocking(&mut ack_buf, ack_timeout_ms, rf_freq) {
+                Ok(Some(len)) => {
+                    if is_ack(&ack_buf[..len]) {
+                        return Ok(());
+                    }
+                }
+                Ok(None)
+                | Err(RadioError::Crc)
+                | Err(RadioError::HeaderCrc)
+                | Err(RadioError::LowSnr) => (),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(RadioError::Timeout)
     }
 
     /// DS, section 13.3.1. Setup DIO1 and DIO3 IRQs, which can be used with the MCU's GPU interrupts.
-    /// We assume DIO2 controls the Tx/Rx switch.
+    /// We assume DIO2 controls the Tx/Rx switch; see `set_irq_dio` to use it for IRQs.
     ///
     /// Sx128x DS:
     /// "In a typical LoRa® Rx operation the user could select one or several of the following IRQ sources:
@@ -995,40 +2260,59 @@ impl Radio {
     /// •RxTxTimeout to indicate that no packet has been detected in a given time frame defined by timeout parameter in the
     /// SetRx() command."
     fn set_irq(&mut self, dio1: &[Irq], dio3: &[Irq]) -> Result<(), RadioError> {
-        let mut irq_word: u16 = 0;
-        let mut dio1_word: u16 = 0;
-        let mut dio3_word: u16 = 0;
+        self.set_irq_dio(dio1, &[], dio3)
+    }
 
-        match self.config {
-            RadioConfig::R6x(_) => {
-                for irq in dio1 {
-                    irq_word |= 1 << (*irq as u16);
-                    dio1_word |= 1 << (*irq as u16);
-                }
-                for irq in dio3 {
-                    irq_word |= 1 << (*irq as u16);
-                    dio3_word |= 1 << (*irq as u16);
-                }
+    /// Map IRQs to each of DIO1, DIO2, and DIO3; eg to distribute TxDone, RxDone, and CadDone across
+    /// three MCU interrupt lines. DIO2 is only available for IRQs on 8x, or on 6x when it isn't used as the
+    /// RF switch; otherwise, passing DIO2 IRQs returns `RadioError::Config`. Note that the Tx and Rx
+    /// functions set their own IRQs, using DIO1 and DIO3. 6x: DS, section 13.3.1. 8x: section 11.9.1.
+    pub fn set_irq_dio(
+        &mut self,
+        dio1: &[Irq],
+        dio2: &[Irq],
+        dio3: &[Irq],
+    ) -> Result<(), RadioError> {
+        self.set_irq_params(&[], dio1, dio2, dio3)
+    }
+
+    /// As `set_irq_dio`, but also enables the `irq_only` IRQs without mapping them to a DIO, so they can be
+    /// read from the IRQ status.
+    fn set_irq_params(
+        &mut self,
+        irq_only: &[Irq],
+        dio1: &[Irq],
+        dio2: &[Irq],
+        dio3: &[Irq],
+    ) -> Result<(), RadioError> {
+        if let RadioConfig::R6x(c) = &self.config {
+            if c.use_dio2_as_rfswitch && !dio2.is_empty() {
+                return Err(RadioError::Config);
             }
-            RadioConfig::R8x(_) => {
-                for irq in dio1 {
-                    irq_word |= 1 << (irq.val_8x());
-                    dio1_word |= 1 << (irq.val_8x());
-                }
-                for irq in dio3 {
-                    irq_word |= 1 << (irq.val_8x());
-                    dio3_word |= 1 << (irq.val_8x());
-                }
+        }
+
+        let mut irq_word: u16 = 0;
+        for irq in irq_only {
+            irq_word |= irq.mask(self.interface.r8x);
+        }
+        let mut dio_words = [0_u16; 3];
+
+        for (dio_word, irqs) in dio_words.iter_mut().zip([dio1, dio2, dio3]) {
+            for irq in irqs {
+                let mask = irq.mask(self.interface.r8x);
+                irq_word |= mask;
+                *dio_word |= mask;
             }
         }
 
         let irq_bytes = irq_word.to_be_bytes();
-        let dio1_bytes = dio1_word.to_be_bytes();
-        let dio3_bytes = dio3_word.to_be_bytes();
+        let dio1_bytes = dio_words[0].to_be_bytes();
+        let dio2_bytes = dio_words[1].to_be_bytes();
+        let dio3_bytes = dio_words[2].to_be_bytes();
 
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::SetDioIrqParams as u8,
-            RadioConfig::R8x(_) => OpCode::SetDioIrqParams.val_8x(),
+            RadioConfig::R8x(_) => OpCode::SetDioIrqParams.val_8x()?,
         };
 
         self.interface.write(&[
@@ -1037,18 +2321,27 @@ impl Radio {
             irq_bytes[1],
             dio1_bytes[0],
             dio1_bytes[1],
-            0, // We don't use DIO2; it's configured as Rx/Tx switch on SX126x.
-            0,
+            dio2_bytes[0],
+            dio2_bytes[1],
             dio3_bytes[0],
             dio3_bytes[1],
         ])
     }
 
+    /// Set the LoRa sync word to one of the standard network values.
+    fn set_sync_word(&mut self, network: LoraNetwork) -> Result<(), RadioError> {
+        self.set_lora_sync_word(network as u16)
+    }
+
+    /// Set the LoRa sync word to an arbitrary value, eg to isolate networks sharing a frequency.
     /// This is a bit confusing, as the register API takes u16, and the sync word is a u16, yet
     /// it's split into two registers.
-    /// 8x: See the note below table 14-54.
-    fn set_sync_word(&mut self, network: LoraNetwork) -> Result<(), RadioError> {
-        let sync_word_bytes = (network as u16).to_be_bytes();
+    ///
+    /// 8x: See the note below table 14-54. The sync word is a single byte, stored in the upper nibble of each
+    /// register; the lower nibbles must be preserved. We use the upper nibble of each byte of `word`, so
+    /// `LoraNetwork` values map to the equivalent 8x sync word. (eg 0x3444 -> 0x34)
+    pub fn set_lora_sync_word(&mut self, word: u16) -> Result<(), RadioError> {
+        let sync_word_bytes = word.to_be_bytes();
 
         match self.config {
             RadioConfig::R6x(_) => {
@@ -1062,56 +2355,546 @@ impl Radio {
                 )?;
             }
             RadioConfig::R8x(_) => {
-                // todo: This isn't quite right: Must be Read/write/modify, leaving bytes 0:3.
-                // self.interface.write_reg_word(
-                //     Register::Reg8x(Register8x::LoraSynchWordA),
-                //     sync_word_bytes[0],
-                // )?;
-                // self.interface.write_reg_word(
-                //     Register::Reg8x(Register8x::LoraSynchWordB),
-                //     sync_word_bytes[1],
-                // )?;
+                for (reg, byte) in [Register8x::LoraSynchWordA, Register8x::LoraSynchWordB]
+                    .into_iter()
+                    .zip(sync_word_bytes)
+                {
+                    let val = self.interface.read_reg_word(Reg8x(reg))?;
+                    self.interface
+                        .write_reg_word(Reg8x(reg), (byte & 0xf0) | (val & 0x0f))?;
+                }
             }
         };
 
         Ok(())
     }
 
+    /// Read back the LoRa sync word; eg to confirm that a public or private network setting took effect.
+    ///
+    /// 8x: Only the upper nibble of each register holds the sync word; see `set_lora_sync_word`. We fill
+    /// the lower nibble of each byte with 4, so the result compares equal to the `LoraNetwork` values.
+    /// (eg 0x34 -> 0x3444)
+    pub fn get_lora_sync_word(&mut self) -> Result<u16, RadioError> {
+        let bytes = match self.config {
+            RadioConfig::R6x(_) => [
+                self.interface
+                    .read_reg_word(Register::Reg6x(Register6x::LoraSyncWordMsb))?,
+                self.interface
+                    .read_reg_word(Register::Reg6x(Register6x::LoraSyncWordLsb))?,
+            ],
+            RadioConfig::R8x(_) => [
+                (self
+                    .interface
+                    .read_reg_word(Reg8x(Register8x::LoraSynchWordA))?
+                    & 0xf0)
+                    | 0x04,
+                (self
+                    .interface
+                    .read_reg_word(Reg8x(Register8x::LoraSynchWordB))?
+                    & 0xf0)
+                    | 0x04,
+            ],
+        };
+
+        Ok(u16::from_be_bytes(bytes))
+    }
+
     /// DS, section 13.3.4
     pub fn clear_irq(&mut self, irqs: &[Irq]) -> Result<(), RadioError> {
         // We use a single 16-bit word, with bits at the various values.
         let mut irq_word: u16 = 0;
         for irq in irqs {
-            let irq_val = match self.config {
-                RadioConfig::R6x(_) => *irq as u16,
-                RadioConfig::R8x(_) => irq.val_8x(),
-            };
-            irq_word |= 1 << irq_val;
+            irq_word |= irq.mask(self.interface.r8x);
         }
 
         let bytes = irq_word.to_be_bytes();
 
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::ClearIrqStatus as u8,
-            RadioConfig::R8x(_) => OpCode::ClearIrqStatus.val_8x(),
+            RadioConfig::R8x(_) => OpCode::ClearIrqStatus.val_8x()?,
         };
         self.interface.write(&[op_code, bytes[0], bytes[1]])
     }
 
+    /// Returns true if the radio's busy pin is high; ie it's not ready for commands. Doesn't block. See
+    /// `Interface::is_busy`.
+    pub fn is_radio_busy(&self) -> bool {
+        self.interface.is_busy()
+    }
+
+    /// Reset the data buffer state, eg to recover after an aborted Tx or Rx, without a full `reinit`: Enter
+    /// standby, re-send the Tx and Rx buffer base addresses, clear all IRQs, and clear the stored payload
+    /// length and start. 6x: DS, sections 13.1.8 and 13.3.4. 8x: sections 11.7.6 and 11.9.3.
+    pub fn reset_fifo(&mut self) -> Result<(), RadioError> {
+        self.set_op_mode(OperatingMode::StbyRc)?;
+        self.write_buffer_base()?;
+        self.clear_all_irq()?;
+
+        self.interface.rx_payload_len = 0;
+        self.interface.rx_payload_start = 0;
+        self.interface.rx_read_ptr = Some(self.rx_base);
+
+        Ok(())
+    }
+
+    /// Set the Tx and Rx base addresses in the radio's data buffer; eg to position payloads when managing the
+    /// buffer manually, such as preloading a retransmission. Any `u8` address is valid, since the buffer is
+    /// 256 bytes. These are stored, and re-sent by `send_payload`, `receive`, `reset_fifo`, and `reinit`.
+    /// Both default to 0. 6x: DS, section 13.4.8. 8x: section 11.7.6.
+    pub fn set_buffer_base(&mut self, tx_addr: u8, rx_addr: u8) -> Result<(), RadioError> {
+        self.tx_base = tx_addr;
+        self.rx_base = rx_addr;
+
+        self.write_buffer_base()
+    }
+
+    /// Send the stored Tx and Rx base addresses to the radio.
+    fn write_buffer_base(&mut self) -> Result<(), RadioError> {
+        let op_code = match self.config {
+            RadioConfig::R6x(_) => OpCode::SetBufferBaseAddress as u8,
+            RadioConfig::R8x(_) => OpCode::SetBufferBaseAddress.val_8x()?,
+        };
+
+        self.interface.write(&[op_code, self.tx_base, self.rx_base])
+    }
+
+    /// Clear every IRQ flag, regardless of which are set.
+    fn clear_all_irq(&mut self) -> Result<(), RadioError> {
+        let op_code = match self.config {
+            RadioConfig::R6x(_) => OpCode::ClearIrqStatus as u8,
+            RadioConfig::R8x(_) => OpCode::ClearIrqStatus.val_8x()?,
+        };
+        self.interface.write(&[op_code, 0xff, 0xff])
+    }
+
     pub fn get_irq_status(&mut self) -> Result<u8, RadioError> {
         self.interface.read_op_word(OpCode::GetIrqStatus)
     }
 
+    /// 8x only. Set the Rx gain to high sensitivity mode, at the cost of increased current draw.
+    /// DS, section 4.2.1.
     pub fn set_high_rx_gain(&mut self) -> Result<(), RadioError> {
         if !self.interface.r8x {
-            panic!("High gain is unavailable on SX126x.")
+            return Err(RadioError::Config);
         }
 
         // Update the word using its default value.
-        let word = 0x25 | (3 << 6);
+        let word = RX_GAIN_DEFAULT_8X | (3 << 6);
         self.interface
             .write_reg_word(Reg8x(Register8x::RxGain), word)
     }
+
+    /// 8x only. Revert the Rx gain to its default, power saving mode, using the register's reset value.
+    /// DS, section 4.2.1.
+    pub fn set_power_saving_rx_gain(&mut self) -> Result<(), RadioError> {
+        if !self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        self.interface
+            .write_reg_word(Reg8x(Register8x::RxGain), RX_GAIN_DEFAULT_8X)
+    }
 }
 
-// todo: Both radios: Should we use SetRxDutyCycle? Come back to later. (A sniff mode.)
+/// For `send`. The payload length is a single byte in packet params, so the payload must be shorter than
+/// the radio's buffer.
+fn check_tx_len(payload_len: usize) -> Result<(), RadioError> {
+    if payload_len >= RADIO_BUF_SIZE {
+        return Err(RadioError::PayloadSize(payload_len));
+    }
+    Ok(())
+}
+
+/// For `cleanup_rx`. Guard against a corrupted received length; eg from a glitched read. It must fit the
+/// configured maximum payload length, and the radio's buffer.
+fn check_rx_len(payload_len: usize, max_payload_len: u8) -> Result<(), RadioError> {
+    if payload_len > max_payload_len as usize || payload_len > RADIO_BUF_SIZE {
+        return Err(RadioError::PayloadSize(payload_len));
+    }
+    Ok(())
+}
+
+/// The operating mode the radio enters after Tx or Rx completes, set by its fallback mode.
+fn fallback_op_mode(fallback: FallbackMode) -> OperatingModeRead {
+    match fallback {
+        FallbackMode::StdbyRc => OperatingModeRead::StbyRc,
+        FallbackMode::StdbyXosc => OperatingModeRead::StbyOsc,
+        FallbackMode::Fs => OperatingModeRead::Fs,
+    }
+}
+
+/// For `cleanup_tx`. After a transmission, the radio should be in its fallback mode, reporting Tx done.
+/// The 8x may report success instead.
+fn tx_status_ok(
+    status: (OperatingModeRead, CommandStatus),
+    fallback: FallbackMode,
+    r8x: bool,
+) -> bool {
+    let cmd_status_ok = match status.1 {
+        CommandStatus::CommandTxDone => true,
+        CommandStatus::CommandProcessSuccess8x => r8x,
+        _ => false,
+    };
+
+    status.0 == fallback_op_mode(fallback) && cmd_status_ok
+}
+
+/// For `cleanup_rx`. After reception, the radio should be in its fallback mode, reporting either a packet,
+/// or a timeout if none arrived.
+fn rx_status_ok(status: (OperatingModeRead, CommandStatus), fallback: FallbackMode) -> bool {
+    let cmd_status_ok = matches!(
+        status.1,
+        CommandStatus::DataAvailable | CommandStatus::CommandTimeout
+    );
+
+    status.0 == fallback_op_mode(fallback) && cmd_status_ok
+}
+
+/// For `cleanup_rx`. Classify the IRQ word read after RxDone, returning the error, and the IRQ that flagged
+/// it. A header error usually indicates mismatched SF, BW, or sync word; a payload CRC error usually
+/// indicates interference or a weak signal. The CRC error IRQ is only meaningful if CRC is enabled.
+///
+/// 8x FLRC: A sync word (address) mismatch means the packet wasn't addressed to us, as opposed to being
+/// corrupted; the payload CRC is separate. DS, Table 11-73.
+fn rx_irq_error(
+    irq_status: u16,
+    r8x: bool,
+    flrc: bool,
+    crc_enabled: bool,
+) -> Option<(RadioError, Irq)> {
+    let flagged = |irq: Irq| irq_status & irq.mask(r8x) != 0;
+
+    if flagged(Irq::HeaderErr) {
+        Some((RadioError::HeaderCrc, Irq::HeaderErr))
+    } else if r8x && flrc && flagged(Irq::SyncWordError) {
+        Some((RadioError::SyncWordMismatch, Irq::SyncWordError))
+    } else if crc_enabled && flagged(Irq::CrcErr) {
+        Some((RadioError::Crc, Irq::CrcErr))
+    } else {
+        None
+    }
+}
+
+/// For `drain_rx`. From the read pointer, and the start and length of the last packet received, find where
+/// the oldest unread packet starts, and how many are unread. `rx_done` distinguishes exactly 256 unread
+/// bytes (the buffer wrapped back to the read pointer) from none.
+fn rx_pending(read_ptr: u8, last_start: u8, len: u8, rx_done: bool) -> (u8, usize) {
+    if len == 0 {
+        return (last_start, 0);
+    }
+
+    let end = last_start.wrapping_add(len);
+    let unread = match end.wrapping_sub(read_ptr) {
+        0 if rx_done => RADIO_BUF_SIZE,
+        0 => return (read_ptr, 0), // Caught up.
+        n => n as usize,
+    };
+
+    let len = len as usize;
+    if unread.is_multiple_of(len) {
+        (read_ptr, unread / len)
+    } else {
+        // Earlier packets had different lengths; we can't find their boundaries.
+        (last_start, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Host tests have no defmt transport; discard output.
+    #[defmt::global_logger]
+    struct NullLogger;
+
+    unsafe impl defmt::Logger for NullLogger {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    defmt::timestamp!("");
+
+    #[test]
+    fn tx_len() {
+        assert_eq!(check_tx_len(0), Ok(()));
+        assert_eq!(check_tx_len(RADIO_BUF_SIZE - 1), Ok(()));
+        assert_eq!(
+            check_tx_len(RADIO_BUF_SIZE),
+            Err(RadioError::PayloadSize(RADIO_BUF_SIZE))
+        );
+        assert_eq!(check_tx_len(1_000), Err(RadioError::PayloadSize(1_000)));
+    }
+
+    #[test]
+    fn rx_len() {
+        assert_eq!(check_rx_len(0, 64), Ok(()));
+        assert_eq!(check_rx_len(64, 64), Ok(()));
+        assert_eq!(check_rx_len(65, 64), Err(RadioError::PayloadSize(65)));
+        // An absurd length, eg from a glitched read.
+        assert_eq!(
+            check_rx_len(4_000, 255),
+            Err(RadioError::PayloadSize(4_000))
+        );
+    }
+
+    #[test]
+    fn validate_fdd_freqs() {
+        let mut c = RadioConfig6x {
+            tx_freq: Some(915_000_000),
+            rx_freq: Some(923_300_000),
+            ..Default::default()
+        };
+        assert_eq!(RadioConfig::R6x(c.clone()).validate(), Ok(()));
+
+        c.rx_freq = Some(2_400_000_000);
+        assert_eq!(
+            RadioConfig::R6x(c).validate(),
+            Err(InvalidConfig(ConfigError::FreqOutOfBand))
+        );
+
+        let c = RadioConfig8x {
+            tx_freq: Some(915_000_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            RadioConfig::R8x(c).validate(),
+            Err(InvalidConfig(ConfigError::FreqOutOfBand))
+        );
+    }
+
+    #[test]
+    fn occupancy_peak() {
+        // The sweep records the loudest reading at each step; a -100dBm channel must read as
+        // quieter than a -50dBm one, and stay below a -90dBm busy threshold.
+        let quiet = status::rssi_inst_dbm(200);
+        let busy = status::rssi_inst_dbm(100);
+        assert_eq!(quiet, -100);
+        assert_eq!(quiet.max(busy), busy);
+        assert!(quiet < -90);
+        assert!(busy > -90);
+    }
+
+    #[test]
+    fn lora_preamble_6x() {
+        let mut c = RadioConfig6x::us915_sf7_bw125();
+        assert_eq!(RadioConfig::R6x(c.clone()).validate(), Ok(()));
+
+        c.packet_params.preamble_len = 10;
+        assert_eq!(RadioConfig::R6x(c.clone()).validate(), Ok(()));
+
+        c.packet_params.preamble_len = 9;
+        assert_eq!(
+            RadioConfig::R6x(c).validate(),
+            Err(InvalidConfig(ConfigError::PreambleLen))
+        );
+    }
+
+    #[test]
+    fn unsupported_packet_type() {
+        let c = RadioConfig8x {
+            packet_type: PacketType8x::Ranging,
+            ..Default::default()
+        };
+        assert_eq!(
+            RadioConfig::R8x(c).validate(),
+            Err(InvalidConfig(ConfigError::PacketType))
+        );
+    }
+
+    #[test]
+    fn output_power_nearest() {
+        let dbm = |dbm, lp| OutputPower6x::nearest(dbm, lp).map(|(_, actual)| actual);
+
+        assert_eq!(dbm(14, false), Some(14));
+        assert_eq!(dbm(21, false), Some(20));
+        assert_eq!(dbm(22, false), Some(22));
+        assert_eq!(dbm(13, false), None);
+
+        // sx1261 levels use the low power PA.
+        assert_eq!(dbm(10, true), Some(10));
+        assert_eq!(dbm(12, true), Some(10));
+        assert_eq!(dbm(13, true), Some(14));
+        assert_eq!(dbm(15, true), Some(15));
+        assert_eq!(dbm(22, true), None);
+        assert_eq!(OutputPower6x::nearest(13, true).unwrap().0.device_sel(), 1);
+    }
+
+    #[test]
+    fn crc_enabled() {
+        let flrc = |crc| {
+            RadioConfig::R8x(RadioConfig8x {
+                packet_type: PacketType8x::Flrc,
+                packet_params: PacketParams::Flrc(params::PacketParamsFlrc {
+                    crc,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+
+        assert!(!flrc(FlrcCrc::CrcOff).crc_enabled());
+        assert!(flrc(FlrcCrc::Crc2Byte).crc_enabled());
+
+        // LoRa defaults to CRC on, on both.
+        assert!(RadioConfig::R6x(Default::default()).crc_enabled());
+        assert!(RadioConfig::R8x(Default::default()).crc_enabled());
+    }
+
+    #[test]
+    fn irq_bits() {
+        use Irq::*;
+
+        // IRQ, 6x bit (DS, Table 13-29), 8x bit (DS, Table 11-73).
+        let table = [
+            (TxDone, Some(0), Some(0)),
+            (RxDone, Some(1), Some(1)),
+            (PremableDetected, Some(2), Some(15)),
+            (SyncWordValid, Some(3), Some(2)),
+            (HeaderValid, Some(4), Some(4)),
+            (HeaderErr, Some(5), Some(5)),
+            (CrcErr, Some(6), Some(6)),
+            (CadDone, Some(7), Some(12)),
+            (CadDetected, Some(8), Some(13)),
+            (Timeout, Some(9), Some(14)),
+            (LrFhssHop, Some(14), None),
+            (SyncWordError, None, Some(3)),
+            (RangingSlaveResponseDone, None, Some(7)),
+            (RangingSlaveRequestDiscard, None, Some(8)),
+            (RangingMasterResultValid, None, Some(9)),
+            (RangingMasterTimeout, None, Some(10)),
+            (RangingSlaveRequestValid, None, Some(11)),
+        ];
+
+        for (irq, bit_6x, bit_8x) in table {
+            assert_eq!(irq.bit(false), bit_6x, "6x {:?}", irq);
+            assert_eq!(irq.bit(true), bit_8x, "8x {:?}", irq);
+            assert_eq!(irq.mask(false), bit_6x.map_or(0, |b| 1 << b));
+        }
+
+        // No two IRQs share a bit.
+        for r8x in [false, true] {
+            let mut word = 0_u16;
+            for (irq, _, _) in table {
+                let mask = irq.mask(r8x);
+                assert_eq!(word & mask, 0, "{:?}", irq);
+                word |= mask;
+            }
+        }
+    }
+
+    #[test]
+    fn tx_status() {
+        let rc = OperatingModeRead::StbyRc;
+
+        assert!(tx_status_ok(
+            (rc, CommandStatus::CommandTxDone),
+            FallbackMode::StdbyRc,
+            false
+        ));
+        assert!(tx_status_ok(
+            (rc, CommandStatus::CommandProcessSuccess8x),
+            FallbackMode::StdbyRc,
+            true
+        ));
+        assert!(!tx_status_ok(
+            (rc, CommandStatus::CommandProcessSuccess8x),
+            FallbackMode::StdbyRc,
+            false
+        ));
+        assert!(!tx_status_ok(
+            (rc, CommandStatus::CommandTimeout),
+            FallbackMode::StdbyRc,
+            false
+        ));
+
+        // The radio is expected in its fallback mode.
+        assert!(tx_status_ok(
+            (OperatingModeRead::Fs, CommandStatus::CommandTxDone),
+            FallbackMode::Fs,
+            true
+        ));
+        assert!(!tx_status_ok(
+            (rc, CommandStatus::CommandTxDone),
+            FallbackMode::Fs,
+            true
+        ));
+    }
+
+    #[test]
+    fn rx_status() {
+        let rc = OperatingModeRead::StbyRc;
+
+        assert!(rx_status_ok(
+            (rc, CommandStatus::DataAvailable),
+            FallbackMode::StdbyRc
+        ));
+        assert!(rx_status_ok(
+            (rc, CommandStatus::CommandTimeout),
+            FallbackMode::StdbyRc
+        ));
+        assert!(!rx_status_ok(
+            (rc, CommandStatus::CommandTxDone),
+            FallbackMode::StdbyRc
+        ));
+        assert!(rx_status_ok(
+            (OperatingModeRead::Fs, CommandStatus::DataAvailable),
+            FallbackMode::Fs
+        ));
+        assert!(!rx_status_ok(
+            (rc, CommandStatus::DataAvailable),
+            FallbackMode::Fs
+        ));
+    }
+
+    #[test]
+    fn rx_irq_classification() {
+        let rx_done = Irq::RxDone.mask(true);
+        let sync_err = Irq::SyncWordError.mask(true);
+        let crc_err = Irq::CrcErr.mask(true);
+
+        // FLRC sync word (address) mismatch.
+        assert_eq!(
+            rx_irq_error(rx_done | sync_err, true, true, true),
+            Some((RadioError::SyncWordMismatch, Irq::SyncWordError))
+        );
+        // It takes priority over a CRC error on the same packet.
+        assert_eq!(
+            rx_irq_error(rx_done | sync_err | crc_err, true, true, true),
+            Some((RadioError::SyncWordMismatch, Irq::SyncWordError))
+        );
+        // Only meaningful for FLRC.
+        assert_eq!(rx_irq_error(rx_done | sync_err, true, false, false), None);
+        // 6x bit 3 is SyncWordValid.
+        assert_eq!(rx_irq_error(1 << 1 | 1 << 3, false, false, true), None);
+
+        assert_eq!(
+            rx_irq_error(rx_done | crc_err, true, true, true),
+            Some((RadioError::Crc, Irq::CrcErr))
+        );
+        assert_eq!(rx_irq_error(rx_done | crc_err, true, true, false), None);
+        assert_eq!(
+            rx_irq_error(rx_done | Irq::HeaderErr.mask(false), false, false, true),
+            Some((RadioError::HeaderCrc, Irq::HeaderErr))
+        );
+        assert_eq!(rx_irq_error(rx_done, true, true, true), None);
+    }
+
+    #[test]
+    fn rx_pending_counts() {
+        // First packet after Rx starts at base 0.
+        assert_eq!(rx_pending(0, 0, 10, true), (0, 1));
+        // Three equal-length packets since the last read.
+        assert_eq!(rx_pending(10, 30, 10, true), (10, 3));
+        // Caught up.
+        assert_eq!(rx_pending(40, 30, 10, false), (40, 0));
+        // Earlier packets of a different length; only the last is recoverable.
+        assert_eq!(rx_pending(0, 30, 7, true), (30, 1));
+        // Wraps past the end of the buffer.
+        assert_eq!(rx_pending(250, 4, 10, true), (250, 2));
+        // Exactly 256 unread bytes; the end lands back on the read pointer.
+        assert_eq!(rx_pending(0, 192, 64, true), (0, 4));
+        assert_eq!(rx_pending(0, 0, 0, true), (0, 0));
+    }
+}
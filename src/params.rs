@@ -2,9 +2,11 @@
 //!
 //! todo: Shared with SX1280, or not?
 
+use crate::shared::RadioError;
+
 /// DS, Table 13-44. Mod param 4.
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum GfskPulseShape {
     NoFilter = 0x00,
@@ -14,16 +16,33 @@ pub enum GfskPulseShape {
     GaussianBt1 = 0x0B,
 }
 
-/// DS, Table 13-45. Mod param 5.
+/// DS, Table 13-45. Mod param 5. Rx bandwidth, in kHz; eg `B117` is 117.3kHz. This should be at least the
+/// Carson bandwidth: 2 * (Fdev + bit rate / 2).
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum GfskBandwidth {
     B48 = 0x1f,
     B58 = 0x17,
     B73 = 0x0f,
     B97 = 0x1e,
-    // todo: Complete A/R
+    B117 = 0x16,
+    B146 = 0x0e,
+    B195 = 0x1d,
+    B234 = 0x15,
+    B293 = 0x0d,
+    B390 = 0x1c,
+    B469 = 0x14,
+    B586 = 0x0c,
+    B782 = 0x1b,
+    B938 = 0x13,
+    B1173 = 0x0b,
+    B1562 = 0x1a,
+    B1872 = 0x12,
+    B2343 = 0x0a,
+    B3120 = 0x19,
+    B3736 = 0x11,
+    B4670 = 0x09,
 }
 
 /// (SX126x) DS, Table 13-47. Mod param 1.
@@ -31,7 +50,7 @@ pub enum GfskBandwidth {
 /// "A higher spreading factor provides better receiver sensitivity at the expense of longer
 /// transmission times (time-on-air)."
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(dead_code)]
 pub enum LoraSpreadingFactor {
     SF5 = 0x05,
@@ -65,6 +84,20 @@ impl LoraSpreadingFactor {
             Self::SF12 => 0xC0,
         }
     }
+
+    /// The minimum SNR, in dB, at which packets can be demodulated. (SX126x) DS, Table 6-1.
+    pub fn snr_threshold_db(&self) -> f32 {
+        match self {
+            Self::SF5 => -2.5,
+            Self::SF6 => -5.,
+            Self::SF7 => -7.5,
+            Self::SF8 => -10.,
+            Self::SF9 => -12.5,
+            Self::SF10 => -15.,
+            Self::SF11 => -17.5,
+            Self::SF12 => -20.,
+        }
+    }
 }
 
 /// DS, Table 13-47. Mod param 2.
@@ -73,7 +106,7 @@ impl LoraSpreadingFactor {
 /// Note that the lower settings here can result in 5s or higher OTA time! OTA seems to scale linearly (inversely)
 /// with bandwidth.
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 pub enum LoraBandwidth6x {
     BW_7 = 0x00,
@@ -90,8 +123,43 @@ pub enum LoraBandwidth6x {
     BW_500 = 0x06,
 }
 
+impl LoraBandwidth6x {
+    /// Bandwidth, in Hz.
+    pub fn hz(&self) -> u32 {
+        match self {
+            Self::BW_7 => 7_810,
+            Self::BW_10 => 10_420,
+            Self::BW_15 => 15_630,
+            Self::BW_20 => 20_830,
+            Self::BW_31 => 31_250,
+            Self::BW_41 => 41_670,
+            Self::BW_62 => 62_500,
+            Self::BW_125 => 125_000,
+            Self::BW_250 => 250_000,
+            Self::BW_500 => 500_000,
+        }
+    }
+
+    /// 10·log10 of the bandwidth in Hz; the noise bandwidth term of the sensitivity formula. Tabulated,
+    /// since we don't have `log10` in `no_std`.
+    fn noise_bw_db(&self) -> f32 {
+        match self {
+            Self::BW_7 => 38.93,
+            Self::BW_10 => 40.18,
+            Self::BW_15 => 41.94,
+            Self::BW_20 => 43.19,
+            Self::BW_31 => 44.95,
+            Self::BW_41 => 46.2,
+            Self::BW_62 => 47.96,
+            Self::BW_125 => 50.97,
+            Self::BW_250 => 53.98,
+            Self::BW_500 => 56.99,
+        }
+    }
+}
+
 #[repr(u8)]
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// Table 14-48. Mod param 2.
 pub enum LoraBandwidth8x {
@@ -101,13 +169,25 @@ pub enum LoraBandwidth8x {
     BW_200 = 0x34,
 }
 
+impl LoraBandwidth8x {
+    /// Bandwidth, in Hz.
+    pub fn hz(&self) -> u32 {
+        match self {
+            Self::BW_1600 => 1_625_000,
+            Self::BW_800 => 812_500,
+            Self::BW_400 => 406_250,
+            Self::BW_200 => 203_125,
+        }
+    }
+}
+
 /// SX126x: DS, Table 13-49. Mod param 3.
 /// SX128x: DS, Table 14-49. Mod param 3.
 /// "A higher coding rate provides better noise immunity at the expense of longer transmission time. In normal conditions a
 /// factor of 4/5 provides the best trade-off; in the presence of strong interfererence a higher coding rate may be used. Error
 /// correction code does not have to be known in advance by the receiver since it is encoded in the header part of the packet."
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 pub enum LoraCodingRate {
     /// raw/total bits: 4/5. Overhead ratio: 1.25
@@ -127,7 +207,7 @@ pub enum LoraCodingRate {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 #[allow(dead_code)]
 /// (SX126x only) Table 13-50. Mod param 4.
 /// "For low data rates (typically for high SF or low BW) and very long payloads which may last several seconds in the air, the low
@@ -141,7 +221,7 @@ pub enum LoraLdrOptimization {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// (SX128x only). Table 14-31.
 pub enum FlrcBitrate {
@@ -160,7 +240,7 @@ pub enum FlrcBitrate {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// (SX128x only). Table 14-32.
 pub enum FlrcCodingRate {
@@ -173,19 +253,64 @@ pub enum FlrcCodingRate {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
-/// (SX128x only). Table 14-33.
+/// (SX128x only). Table 14-33. Also used as GFSK mod param 3 (modulation shaping).
 pub enum FlrcBt {
     Disabled = 0x00,
     BT_1 = 0x10,
     BT_0_5 = 0x20,
 }
 
-#[derive(Clone)]
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(non_camel_case_types, dead_code)]
+/// (SX128x only). GFSK mod param 1. DS, section 14.2. Bit rate in Mb/s, and bandwidth in Mhz.
+pub enum GfskBitrateBandwidth8x {
+    BR_2_0_BW_2_4 = 0x04,
+    BR_1_6_BW_2_4 = 0x28,
+    BR_1_0_BW_2_4 = 0x4c,
+    BR_1_0_BW_1_2 = 0x45,
+    BR_0_8_BW_2_4 = 0x70,
+    BR_0_8_BW_1_2 = 0x69,
+    BR_0_5_BW_1_2 = 0x8d,
+    BR_0_5_BW_0_6 = 0x86,
+    BR_0_4_BW_1_2 = 0xb1,
+    BR_0_4_BW_0_6 = 0xaa,
+    BR_0_25_BW_0_6 = 0xce,
+    BR_0_25_BW_0_3 = 0xc7,
+    BR_0_125_BW_0_3 = 0xef,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(non_camel_case_types, dead_code)]
+/// (SX128x only). GFSK mod param 2. DS, section 14.2. Modulation index; frequency deviation is
+/// this multiplied by half the bit rate.
+pub enum GfskModIndex8x {
+    MI_0_35 = 0x00,
+    MI_0_5 = 0x01,
+    MI_0_75 = 0x02,
+    MI_1_0 = 0x03,
+    MI_1_25 = 0x04,
+    MI_1_5 = 0x05,
+    MI_1_75 = 0x06,
+    MI_2_0 = 0x07,
+    MI_2_25 = 0x08,
+    MI_2_5 = 0x09,
+    MI_2_75 = 0x0a,
+    MI_3_0 = 0x0b,
+    MI_3_25 = 0x0c,
+    MI_3_5 = 0x0d,
+    MI_3_75 = 0x0e,
+    MI_4_0 = 0x0f,
+}
+
+#[derive(Clone, defmt::Format)]
 pub enum ModulationParams8x {
     Lora(ModulationParamsLora8x),
     Flrc(ModulationParamsFlrc),
+    Gfsk(ModulationParamsGfsk8x),
 }
 
 impl Default for ModulationParams8x {
@@ -195,7 +320,7 @@ impl Default for ModulationParams8x {
 }
 
 /// (126x) See DS, section 6.1.1: Modulation Parameter.
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 pub struct ModulationParamsLora6x {
     /// Param 1
     pub mod_bandwidth: LoraBandwidth6x,
@@ -225,8 +350,107 @@ impl Default for ModulationParamsLora6x {
     }
 }
 
+/// Receiver noise figure, in dB, assumed by `sensitivity_dbm`.
+const NOISE_FIGURE_6X: f32 = 6.;
+
+/// DS, section 13.4.5.2: LDRO is recommended when symbol time is at or above 16.38ms.
+const LDRO_SYMBOL_TIME_US: u32 = 16_380;
+
+/// BW_250 and BW_500 may not be available below this RF frequency, in Hz.
+const WIDE_BW_MIN_FREQ: u32 = 400_000_000;
+
+impl ModulationParamsLora6x {
+    /// Check for combinations the datasheet flags as problematic: Wide bandwidths below 400Mhz, and
+    /// long symbol times (high SF, low BW) without low data rate optimization, which cause unreliable
+    /// reception. Run by `set_mod_params`, so misconfigurations are caught at setup.
+    pub fn validate(&self, rf_freq: u32) -> Result<(), RadioError> {
+        if rf_freq < WIDE_BW_MIN_FREQ
+            && matches!(
+                self.mod_bandwidth,
+                LoraBandwidth6x::BW_250 | LoraBandwidth6x::BW_500
+            )
+        {
+            return Err(RadioError::Config);
+        }
+
+        let symbol_time_us =
+            (1_u64 << self.spreading_factor as u8) * 1_000_000 / self.mod_bandwidth.hz() as u64;
+
+        if symbol_time_us >= LDRO_SYMBOL_TIME_US as u64
+            && self.low_data_rate_optimization == LoraLdrOptimization::Disabled
+        {
+            return Err(RadioError::Config);
+        }
+
+        Ok(())
+    }
+
+    /// A rough estimate of receiver sensitivity, in dBm, for link budget calculations:
+    /// -174 + 10·log10(BW) + NF + SNR threshold. (DS, section 6.1.1). -174dBm/Hz is thermal noise at room
+    /// temperature. This assumes a noise figure of 6dB, and ignores coding rate, LDRO, and the boosted
+    /// Rx gain setting; compare against the DS sensitivity tables for your configuration.
+    pub fn sensitivity_dbm(&self) -> f32 {
+        -174.
+            + self.mod_bandwidth.noise_bw_db()
+            + NOISE_FIGURE_6X
+            + self.spreading_factor.snr_threshold_db()
+    }
+
+    /// Time on air of a packet, in ms. DS, section 6.1.4.
+    pub fn time_on_air_ms(&self, packet_params: &PacketParamsLora, payload_len: u8) -> f32 {
+        let sf = self.spreading_factor as i32;
+        // 4/5 to 4/8 -> 1 to 4. (The interleaved rates are 8x only; we use the equivalent FEC.)
+        let cr = match self.coding_rate {
+            LoraCodingRate::CR_4_5 | LoraCodingRate::CR_LI_4_5 => 1,
+            LoraCodingRate::CR_4_6 | LoraCodingRate::CR_LI_4_6 => 2,
+            LoraCodingRate::CR_4_7 => 3,
+            LoraCodingRate::CR_4_8 | LoraCodingRate::CR_LI_4_8 => 4,
+        };
+
+        let crc_bits = match packet_params.crc_enabled {
+            CrcEnabled::Enabled => 16,
+            CrcEnabled::Disabled => 0,
+        };
+        let header_bits = match packet_params.header_type {
+            LoraHeaderType::VariableLength => 20,
+            LoraHeaderType::FixedLength => 0,
+        };
+
+        // SF5 and SF6 have a longer preamble overhead, and no extra 8 payload bits.
+        let (overhead_syms, extra_bits) = if sf < 7 { (6.25, 0) } else { (4.25, 8) };
+
+        let bits_per_sym = match self.low_data_rate_optimization {
+            LoraLdrOptimization::Enabled => 4 * (sf - 2),
+            LoraLdrOptimization::Disabled => 4 * sf,
+        };
+
+        let payload_bits = 8 * payload_len as i32 + crc_bits - 4 * sf + extra_bits + header_bits;
+        // Ceiling division.
+        let payload_syms = if payload_bits > 0 {
+            (payload_bits + bits_per_sym - 1) / bits_per_sym * (cr + 4)
+        } else {
+            0
+        };
+
+        let num_syms = packet_params.preamble_len as f32 + overhead_syms + 8. + payload_syms as f32;
+        let symbol_time_ms = (1_u32 << sf) as f32 * 1_000. / self.mod_bandwidth.hz() as f32;
+
+        num_syms * symbol_time_ms
+    }
+
+    /// The largest payload, in bytes, whose time on air fits within `max_ms`; eg to size frames for a
+    /// duty cycle budget. Returns 0 if even an empty packet exceeds it. The inverse of `time_on_air_ms`.
+    pub fn max_payload_for_airtime(&self, packet_params: &PacketParamsLora, max_ms: f32) -> u8 {
+        // Time on air increases with payload length, so search down from the largest.
+        (0..=u8::MAX)
+            .rev()
+            .find(|&len| self.time_on_air_ms(packet_params, len) <= max_ms)
+            .unwrap_or(0)
+    }
+}
+
 /// (126x) See DS, section 6.1.1: Modulation Parameter.
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 pub struct ModulationParamsLora8x {
     pub mod_bandwidth: LoraBandwidth8x,
     pub spreading_factor: LoraSpreadingFactor,
@@ -244,7 +468,7 @@ impl Default for ModulationParamsLora8x {
 }
 
 /// 8x only: See table 14-31.
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 pub struct ModulationParamsFlrc {
     /// Param 1
     pub bitrate: FlrcBitrate,
@@ -265,12 +489,58 @@ impl Default for ModulationParamsFlrc {
     }
 }
 
+/// 6x only. DS, section 13.4.5.1.
+#[derive(Clone, defmt::Format)]
+pub struct ModulationParamsGfsk6x {
+    /// Params 1 - 3. Bit rate, in b/s. (600 - 300,000)
+    pub bitrate: u32,
+    /// Param 4
+    pub pulse_shape: GfskPulseShape,
+    /// Param 5
+    pub bandwidth: GfskBandwidth,
+    /// Params 6 - 8. Frequency deviation, in Hz.
+    pub fdev: u32,
+}
+
+impl Default for ModulationParamsGfsk6x {
+    fn default() -> Self {
+        Self {
+            bitrate: 50_000,
+            pulse_shape: GfskPulseShape::GaussianBt0_5,
+            // Carson bandwidth: 2 * (25kHz + 25kHz) = 100kHz.
+            bandwidth: GfskBandwidth::B117,
+            fdev: 25_000,
+        }
+    }
+}
+
+/// 8x only: See DS, section 14.2.
+#[derive(Clone, defmt::Format)]
+pub struct ModulationParamsGfsk8x {
+    /// Param 1
+    pub bitrate_bandwidth: GfskBitrateBandwidth8x,
+    /// Param 2
+    pub mod_index: GfskModIndex8x,
+    /// Param 3
+    pub bt: FlrcBt,
+}
+
+impl Default for ModulationParamsGfsk8x {
+    fn default() -> Self {
+        Self {
+            bitrate_bandwidth: GfskBitrateBandwidth8x::BR_1_0_BW_1_2,
+            mod_index: GfskModIndex8x::MI_0_5,
+            bt: FlrcBt::BT_0_5,
+        }
+    }
+}
+
 /// SX126x DS, Table 13-67. Packet param 3.
 /// SX128x DS, Table 14-51. Packet param 2.
 /// Also, Section 6.1.3. "The LoRa® modem employs two types of packet formats: explicit and implicit. The explicit
 /// packet includes a short header
 /// that contains information about the number of bytes, coding rate and whether a CRC is used in the packet."
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 pub enum LoraHeaderType {
     /// Explict header
     VariableLength,
@@ -294,7 +564,7 @@ impl LoraHeaderType {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// (SX128x only). Packet param 1. Table 14-34. 8 Bit is the min for 1Mb/s bit rate. Min 16 otherwise.
 pub enum FlrcPreambleLen {
@@ -309,7 +579,7 @@ pub enum FlrcPreambleLen {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// (SX128x only). Packet param 2 Table 14-35.
 /// The number of bytes used for Sync Word is defined by packetParam2. The user can rely on the built-in 21-bit preamble
@@ -323,9 +593,9 @@ pub enum FlrcSyncWordLen {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
-/// (SX128x only). Packet param 3. Table 14-36.
+/// (SX128x only). Packet param 3. Table 14-36. Also used as GFSK packet param 3.
 /// "A configurable number of bit-errors can be tolerated in the Sync Word. The desired number of bit errors permissible is
 /// written to Synch Address Control register 0x9CD: this is a direct binary mapping with 0 meaning no error is tolerated
 /// and 15 meaning up to 15 bit errors will be tolerated.
@@ -343,16 +613,16 @@ pub enum FlrcSyncWordCombo {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
-/// (SX128x only). Packet param 4. Table 14-37.
+/// (SX128x only). Packet param 4. Table 14-37. Also used as GFSK packet param 4.
 pub enum FlrcPacketType {
     LenFixed = 0x00,
     LenVariable = 0x20,
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
 #[allow(non_camel_case_types, dead_code)]
 /// (SX128x only). Packet param 6. Table 14-39.
 pub enum FlrcCrc {
@@ -362,8 +632,113 @@ pub enum FlrcCrc {
     Crc4Byte = 0x30,
 }
 
+impl FlrcCrc {
+    /// The largest polynomial or seed this CRC length can hold; 0 if the CRC is off.
+    pub(crate) fn max_value(&self) -> u32 {
+        match self {
+            Self::CrcOff => 0,
+            Self::Crc2Byte => 0xffff,
+            Self::Crc3Byte => 0xff_ffff,
+            Self::Crc4Byte => 0xffff_ffff,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(non_camel_case_types, dead_code)]
+/// (SX128x only). GFSK packet param 1. DS, section 14.2.
+pub enum GfskPreambleLen8x {
+    /// 4 bits, etc
+    P4 = 0x00,
+    P8 = 0x10,
+    P12 = 0x20,
+    P16 = 0x30,
+    P20 = 0x40,
+    P24 = 0x50,
+    P28 = 0x60,
+    P32 = 0x70,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX128x only). GFSK packet param 2. DS, section 14.2. Sync word length, in bytes.
+pub enum GfskSyncWordLen8x {
+    B1 = 0x00,
+    B2 = 0x02,
+    B3 = 0x04,
+    B4 = 0x06,
+    B5 = 0x08,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX128x only). GFSK packet param 6. DS, section 14.2. Note that these values differ in meaning
+/// from the FLRC ones.
+pub enum GfskCrc8x {
+    CrcOff = 0x00,
+    Crc1Byte = 0x10,
+    Crc2Byte = 0x20,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX128x only). GFSK packet param 7. DS, section 14.2.
+pub enum Whitening8x {
+    Enabled = 0x00,
+    Disabled = 0x08,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX126x only). GFSK packet param 3. DS, Table 13-67. The number of preamble bits the receiver
+/// must detect before searching for the sync word. Longer detectors reduce false detections on noise,
+/// at the cost of sensitivity. Must be no longer than the transmitted preamble.
+pub enum GfskPreambleDetector6x {
+    Off = 0x00,
+    Bits8 = 0x04,
+    Bits16 = 0x05,
+    Bits24 = 0x06,
+    Bits32 = 0x07,
+}
+
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX126x only). GFSK packet param 5. DS, Table 13-69.
+pub enum GfskAddrComp6x {
+    Disabled = 0x00,
+    Node = 0x01,
+    NodeAndBroadcast = 0x02,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX126x only). GFSK packet param 6. DS, Table 13-70.
+pub enum GfskPacketType6x {
+    LenFixed = 0x00,
+    LenVariable = 0x01,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX126x only). GFSK packet param 8. DS, Table 13-71.
+pub enum GfskCrc6x {
+    CrcOff = 0x01,
+    Crc1Byte = 0x00,
+    Crc2Byte = 0x02,
+    Crc1ByteInv = 0x04,
+    Crc2ByteInv = 0x06,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
 pub enum CrcEnabled {
     Disabled,
     Enabled,
@@ -386,7 +761,7 @@ impl CrcEnabled {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
 pub enum InvertIq {
     Standard,
     Inverted,
@@ -408,11 +783,13 @@ impl InvertIq {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 /// Only used on 8x; we force Lora for 6x at this time.
 pub enum PacketParams {
     Lora(PacketParamsLora),
     Flrc(PacketParamsFlrc),
+    Gfsk(PacketParamsGfsk8x),
+    Ble(PacketParamsBle),
 }
 
 impl Default for PacketParams {
@@ -423,7 +800,7 @@ impl Default for PacketParams {
 
 /// (sx126x)See DS, section 13.4.6.2.
 /// (sx128x)See DS, see starting at table 14-51.
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 pub struct PacketParamsLora {
     /// The LoRa® packet starts with a preamble sequence which is used to synchronize the receiver with the incoming signal. By
     /// default the packet is configured with a 12-symbol long sequence. This is a programmable variable so the preamble length
@@ -439,8 +816,22 @@ pub struct PacketParamsLora {
     /// Sx1280: Packet param 3.
     pub payload_len: u8,
     pub crc_enabled: CrcEnabled,
-    /// Sx1280. Packet param 5.
+    /// IQ polarity used when transmitting. Sx1280. Packet param 5.
     pub invert_iq: InvertIq,
+    /// IQ polarity used when receiving; `None` uses `invert_iq`. LoRaWAN end devices, for example, transmit
+    /// uplinks with standard IQ, and receive downlinks with inverted IQ. On 6x, `receive` also sets or reverts
+    /// the IQ polarity errata fix to match (DS, section 15.4), so no extra steps are needed for LoRaWAN downlinks.
+    pub invert_iq_rx: Option<InvertIq>,
+}
+
+impl PacketParamsLora {
+    /// The IQ polarity to use when receiving (`rx`), or transmitting.
+    pub fn iq_for_dir(&self, rx: bool) -> InvertIq {
+        match self.invert_iq_rx {
+            Some(iq) if rx => iq,
+            _ => self.invert_iq,
+        }
+    }
 }
 
 impl Default for PacketParamsLora {
@@ -451,12 +842,13 @@ impl Default for PacketParamsLora {
             payload_len: 0, // This is set during transmission.
             crc_enabled: CrcEnabled::Enabled,
             invert_iq: InvertIq::Standard,
+            invert_iq_rx: None,
         }
     }
 }
 
 /// 8x only. DS, Table 14-34
-#[derive(Clone)]
+#[derive(Clone, defmt::Format)]
 pub struct PacketParamsFlrc {
     /// Param 1
     pub preamble_len: FlrcPreambleLen,
@@ -485,4 +877,240 @@ impl Default for PacketParamsFlrc {
     }
 }
 
-// todo: CAD params A/R.
+/// 6x only. DS, section 13.4.6.1.
+#[derive(Clone, defmt::Format)]
+pub struct PacketParamsGfsk6x {
+    /// Params 1 and 2. Preamble length, in bits.
+    pub preamble_len: u16,
+    /// Param 3
+    pub preamble_detector: GfskPreambleDetector6x,
+    /// Param 4. Sync word length, in bits. (0 - 64)
+    pub sync_word_len: u8,
+    /// Param 5
+    pub addr_comp: GfskAddrComp6x,
+    /// Param 6
+    pub packet_type: GfskPacketType6x,
+    /// Param 7
+    pub payload_len: u8,
+    /// Param 8
+    pub crc: GfskCrc6x,
+    /// Param 9
+    pub whitening: bool,
+}
+
+impl Default for PacketParamsGfsk6x {
+    fn default() -> Self {
+        Self {
+            preamble_len: 32,
+            preamble_detector: GfskPreambleDetector6x::Bits16, // Semtech's recommendation.
+            sync_word_len: 32,
+            addr_comp: GfskAddrComp6x::Disabled,
+            packet_type: GfskPacketType6x::LenVariable,
+            payload_len: 0,
+            crc: GfskCrc6x::Crc2Byte,
+            whitening: true,
+        }
+    }
+}
+
+/// 8x only. DS, section 14.2.
+#[derive(Clone, defmt::Format)]
+pub struct PacketParamsGfsk8x {
+    /// Param 1
+    pub preamble_len: GfskPreambleLen8x,
+    /// Param 2
+    pub sync_word_len: GfskSyncWordLen8x,
+    /// Param 3
+    pub sync_word_combo: FlrcSyncWordCombo,
+    /// Param 4
+    pub packet_type: FlrcPacketType,
+    /// Param 5
+    pub payload_len: u8,
+    /// Param 6
+    pub crc: GfskCrc8x,
+    /// Param 7
+    pub whitening: Whitening8x,
+}
+
+impl Default for PacketParamsGfsk8x {
+    fn default() -> Self {
+        Self {
+            preamble_len: GfskPreambleLen8x::P16,
+            sync_word_len: GfskSyncWordLen8x::B4,
+            sync_word_combo: FlrcSyncWordCombo::SyncWord1,
+            packet_type: FlrcPacketType::LenVariable,
+            payload_len: 0,
+            crc: GfskCrc8x::Crc2Byte,
+            whitening: Whitening8x::Enabled,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(non_camel_case_types, dead_code)]
+/// (SX128x only). BLE packet param 1. DS, section 14.6. Sets the maximum PDU payload length.
+pub enum BleConnectionState {
+    /// Bluetooth 4.1 and earlier.
+    MaxLen31 = 0x00,
+    /// Bluetooth 4.1 and earlier, with advertising payloads.
+    MaxLen37 = 0x20,
+    TxTestMode = 0x40,
+    /// Bluetooth 4.2
+    MaxLen255 = 0x80,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// (SX128x only). BLE packet param 2. DS, section 14.6.
+pub enum BleCrc {
+    Off = 0x00,
+    Crc3Byte = 0x10,
+}
+
+/// 8x only. DS, section 14.6. The payload length isn't set here; it's part of the PDU header.
+#[derive(Clone, defmt::Format)]
+pub struct PacketParamsBle {
+    /// Param 1
+    pub connection_state: BleConnectionState,
+    /// Param 2
+    pub crc: BleCrc,
+    /// Param 4. (Param 3 selects a test payload, which we don't use.)
+    pub whitening: Whitening8x,
+}
+
+impl Default for PacketParamsBle {
+    fn default() -> Self {
+        Self {
+            connection_state: BleConnectionState::MaxLen37,
+            crc: BleCrc::Crc3Byte,
+            whitening: Whitening8x::Enabled,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, defmt::Format)]
+#[allow(dead_code)]
+/// Number of symbols used for channel activity detection. 6x: DS, Table 13-80. 8x: Table 11-44.
+pub enum CadSymbolNum {
+    S1,
+    S2,
+    S4,
+    S8,
+    S16,
+}
+
+impl CadSymbolNum {
+    pub fn val_6x(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn val_8x(&self) -> u8 {
+        (*self as u8) << 5
+    }
+}
+
+#[derive(Clone, Copy, defmt::Format)]
+/// What the radio does after a CAD operation. 6x: DS, Table 13-81.
+pub enum CadExitMode {
+    /// Return to STDBY_RC once CAD is complete.
+    StandbyRc,
+    /// If activity is detected, begin receiving, with this timeout in ms; otherwise return to STDBY_RC.
+    /// This lets a single command scan for, and receive a packet; efficient for duty-cycled receivers.
+    /// 6x only.
+    Rx(f32),
+}
+
+/// LoRa channel activity detection parameters. 6x: DS, section 13.4.7. 8x: section 11.7.9.
+/// Detection peak and min are 6x only; the best values depend on spreading factor and bandwidth.
+/// See Semtech AN1200.48.
+#[derive(Clone, defmt::Format)]
+pub struct CadParams {
+    pub symbol_num: CadSymbolNum,
+    pub det_peak: u8,
+    pub det_min: u8,
+    pub exit_mode: CadExitMode,
+}
+
+impl CadParams {
+    /// Symbol count and detection thresholds suited to a spreading factor, from Semtech AN1200.48.
+    /// Higher spreading factors need more symbols for reliable detection.
+    pub fn for_sf(sf: LoraSpreadingFactor) -> Self {
+        let (symbol_num, det_peak) = match sf {
+            LoraSpreadingFactor::SF5
+            | LoraSpreadingFactor::SF6
+            | LoraSpreadingFactor::SF7
+            | LoraSpreadingFactor::SF8 => (CadSymbolNum::S2, 22),
+            LoraSpreadingFactor::SF9 => (CadSymbolNum::S4, 23),
+            LoraSpreadingFactor::SF10 => (CadSymbolNum::S4, 24),
+            LoraSpreadingFactor::SF11 => (CadSymbolNum::S4, 25),
+            LoraSpreadingFactor::SF12 => (CadSymbolNum::S8, 28),
+        };
+
+        Self {
+            symbol_num,
+            det_peak,
+            det_min: 10,
+            exit_mode: CadExitMode::StandbyRc,
+        }
+    }
+}
+
+impl Default for CadParams {
+    fn default() -> Self {
+        Self {
+            symbol_num: CadSymbolNum::S2,
+            det_peak: 22,
+            det_min: 10,
+            exit_mode: CadExitMode::StandbyRc,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flrc_crc_max() {
+        assert_eq!(FlrcCrc::CrcOff.max_value(), 0);
+        assert_eq!(FlrcCrc::Crc2Byte.max_value(), 0xffff);
+        assert_eq!(FlrcCrc::Crc3Byte.max_value(), 0xff_ffff);
+        assert_eq!(FlrcCrc::Crc4Byte.max_value(), 0xffff_ffff);
+    }
+
+    #[test]
+    fn sensitivity() {
+        // SX1262 DS sensitivity: About -124 to -125dBm at SF7, BW125, and -137dBm at SF12, BW125.
+        for (sf, ds_dbm) in [
+            (LoraSpreadingFactor::SF7, -124.5),
+            (LoraSpreadingFactor::SF12, -137.),
+        ] {
+            let params = ModulationParamsLora6x {
+                mod_bandwidth: LoraBandwidth6x::BW_125,
+                spreading_factor: sf,
+                ..Default::default()
+            };
+            let est = params.sensitivity_dbm();
+            assert!((est - ds_dbm).abs() <= 3., "{}", est);
+        }
+    }
+
+    #[test]
+    fn iq_rx_follows_tx() {
+        let mut p = PacketParamsLora {
+            invert_iq: InvertIq::Inverted,
+            ..Default::default()
+        };
+        assert!(p.iq_for_dir(true) == InvertIq::Inverted);
+        assert!(p.iq_for_dir(false) == InvertIq::Inverted);
+
+        // LoRaWAN end device: standard uplinks, inverted downlinks.
+        p.invert_iq = InvertIq::Standard;
+        p.invert_iq_rx = Some(InvertIq::Inverted);
+        assert!(p.iq_for_dir(true) == InvertIq::Inverted);
+        assert!(p.iq_for_dir(false) == InvertIq::Standard);
+    }
+}
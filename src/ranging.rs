@@ -0,0 +1,39 @@
+//! Code related to ranging on SX128x.
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
+use crate::{
+    shared::{RadioError, Register::Reg8x, Register8x},
+    Radio,
+};
+
+/// DS, Table 13-1. Valid range of the ranging filter window size register.
+const RANGING_FILTER_WINDOW_MIN: u8 = 8;
+
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
+    /// 8x only. Set the number of ranging samples averaged into the ranging result. DS, section 14.5.3,
+    /// and Table 13-1. Larger windows reduce noise in distance estimates, at the cost of latency.
+    /// Valid from 8 to 255. (Default: 127)
+    pub fn set_ranging_filter(&mut self, window: u8) -> Result<(), RadioError> {
+        if !self.interface.r8x || window < RANGING_FILTER_WINDOW_MIN {
+            return Err(RadioError::Config);
+        }
+
+        self.interface
+            .write_reg_word(Reg8x(Register8x::RangingFilterWindowSize), window)
+    }
+
+    /// 8x only. Clear the samples accumulated in the ranging filter. Setting bit 6 of this register
+    /// resets the filter; other bits are preserved.
+    pub fn reset_ranging_filter(&mut self) -> Result<(), RadioError> {
+        if !self.interface.r8x {
+            return Err(RadioError::Config);
+        }
+
+        let val = self
+            .interface
+            .read_reg_word(Reg8x(Register8x::ResetRangingFilter))?;
+        self.interface
+            .write_reg_word(Reg8x(Register8x::ResetRangingFilter), val | (1 << 6))
+    }
+}
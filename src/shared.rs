@@ -2,9 +2,9 @@ use hal::gpio::Pin;
 
 use crate::{CommandStatus, OperatingModeRead};
 
+/// Chip select isn't included here; it's handled by the `SpiDevice` passed to `Radio::new`.
 #[derive(Clone)]
 pub struct RadioPins {
-    pub cs: Pin,
     pub busy: Pin,
     pub reset: Pin,
 }
@@ -15,9 +15,34 @@ pub fn split_addr(addr: u16) -> (u8, u8) {
     (result[0], result[1])
 }
 
+/// Compare two byte slices without returning early on the first mismatch. Use this for software
+/// address or sync word matching, to keep timing side channels out of the filter path.
+/// Slices of different lengths are never equal; the length itself is not treated as secret.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+
+    // Prevents the compiler from reasoning about `diff`, and short-circuiting the loop above.
+    core::hint::black_box(diff) == 0
+}
+
+/// 8x only. The receiver's intermediate frequency, in Hz. This is fixed in silicon; there's no command
+/// or register to change it. The radio applies it internally when receiving, so the configured `rf_freq`
+/// is the effective Rx frequency, and no correction is required. DS, Table 11-40.
+pub const IF_FREQ_8X: u32 = 1_300_000;
+
 // todo: Experiment.
 pub const MAX_ITERS: u32 = 400_000;
 
+/// The DS-recommended 6x Rx gain retention register values; applied on init. DS, section 9.6.
+pub const RX_GAIN_RETENTION_DEFAULT_6X: [u8; 3] = [0x01, 0x08, 0xac];
+
 // todo: Make sure this generalizes to 1280
 /// Error types associated with the radio and this library.
 #[derive(Debug, defmt::Format, PartialEq)]
@@ -29,14 +54,56 @@ pub enum RadioError {
     Status((OperatingModeRead, CommandStatus)),
     /// Invalid CRC, as reported by IRQ status,
     Crc,
+    /// Invalid LoRa header CRC, as reported by IRQ status. Often indicates a configuration mismatch.
+    HeaderCrc,
+    /// (8x FLRC) The received sync word didn't match any enabled one, as reported by IRQ status; ie the
+    /// packet wasn't addressed to us, as opposed to a payload CRC error.
+    SyncWordMismatch,
+    /// A packet was received with an SNR below the configured `min_snr_db`.
+    LowSnr,
+    /// A packet was received with an RSSI below the configured `min_rssi_dbm`.
+    LowRssi,
     /// Device error
     Device,
     /// An unacceptable value is configured.
     Config,
+    /// The modulation or packet params variant doesn't match the configured packet type.
+    ParamMismatch,
     FirmwareVersion,
     PayloadSize(usize),
     BusyTimeout,
+    /// The PLL failed to lock when entering FS, Tx, or Rx mode.
+    PllLock,
+    /// A Tx or Rx operation didn't complete in the allotted time.
+    Timeout,
     UnexpectedStatus(u8),
+    /// The config failed validation by `RadioConfig::validate`.
+    InvalidConfig(ConfigError),
+    /// A `TxQueue` has no room for another payload.
+    QueueFull,
+}
+
+/// A specific reason a config failed `RadioConfig::validate`.
+#[derive(Debug, defmt::Format, PartialEq)]
+pub enum ConfigError {
+    /// `rf_freq`, or `tx_freq` or `rx_freq` if set, is outside the family's band.
+    /// (6x: 150 - 960Mhz. 8x: 2_400 - 2_500Mhz)
+    FreqOutOfBand,
+    /// The preamble length is 0, or (8x LoRa) can't be represented as `mantissa * 2^exponent`, with a
+    /// mantissa of 1 - 15.
+    PreambleLen,
+    /// Output power is outside the PA's range. (8x: -18 to +13 dBm)
+    OutputPower,
+    /// Implicit (fixed-length) LoRa header mode is configured with a payload length of 0.
+    ImplicitPayloadLen,
+    /// The LoRa modulation params fail `ModulationParamsLora6x::validate`.
+    LoraModulation,
+    /// The modulation or packet params variant doesn't match the packet type.
+    ParamMismatch,
+    /// The fallback mode isn't supported by this radio. (8x: `StdbyXosc`)
+    FallbackMode,
+    /// The packet type isn't supported by this driver yet. (8x: `Ranging`)
+    PacketType,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -94,39 +161,68 @@ pub enum OpCode {
     // SetUartSpeed = 0x9d,
     SetRangingRole = 0xa3,
     SetAdvancedRnaging = 0x91,
+    SetAutoFs = 0x9e,
 }
 
 impl OpCode {
-    /// Register value for sx128x. 126x uses the u8 value for now, for backwards compat.
-    /// The same spreading factors are available on both, but with reversed reg hex positions.
-    /// todo: Commented-out lines are present on 126x, but absent on sx128x.
-    pub fn val_8x(&self) -> u8 {
-        match self {
+    /// Opcode for sx128x; DS, Table 11-5. The `OpCode` discriminants are the sx126x values.
+    /// Every opcode is listed explicitly, so a 6x value is never sent to an 8x radio by accident.
+    /// Opcodes with the same value on both: GetStatus, SetSleep, SetStandby, SetFS, SetTx, SetRx,
+    /// SetRxDutyCycle, SetCAD, SetTxContinuousWave, SetTxContinuousPremable, SetPacketType,
+    /// SetRfFrequency, SetTxParams, SetCADParams, SetBufferBaseAddress, SetModulationParams,
+    /// SetPacketParams, and SetRegulatorMode.
+    /// Returns `RadioError::Config` for opcodes absent on 8x.
+    pub fn val_8x(&self) -> Result<u8, RadioError> {
+        Ok(match self {
+            Self::GetStatus => 0xc0,
             Self::WriteRegister => 0x18,
             Self::ReadRegister => 0x19,
             Self::WriteBuffer => 0x1a,
             Self::ReadBuffer => 0x1b,
+            Self::SetSleep => 0x84,
+            Self::SetStandby => 0x80,
+            Self::SetFS => 0xc1,
+            Self::SetTx => 0x83,
+            Self::SetRx => 0x82,
+            Self::SetRxDutyCycle => 0x94,
+            Self::SetCAD => 0xc5,
+            Self::SetTxContinuousWave => 0xd1,
+            Self::SetTxContinuousPremable => 0xd2,
+            Self::SetPacketType => 0x8a,
             Self::GetPacketType => 0x03,
-            // Self::SetPAConfig => 0x95,
+            Self::SetRfFrequency => 0x86,
+            Self::SetTxParams => 0x8e,
+            Self::SetCADParams => 0x88,
+            Self::SetBufferBaseAddress => 0x8f,
+            Self::SetModulationParams => 0x8b,
+            Self::SetPacketParams => 0x8c,
             Self::GetRxBufferStatus => 0x17,
             Self::GetPacketStatus => 0x1d,
             Self::GetRSSIInst => 0x1f,
-            // Self::GetStats => 0x10,
-            // Self::ResetStats => 0x00,
             Self::SetDioIrqParams => 0x8d,
             Self::GetIrqStatus => 0x15,
             Self::ClearIrqStatus => 0x97,
-            // Self::Calibrate => 0x89,
-            // Self::CalibrateImage => 0x98,
-            // Self::GetDeviceErrors => 0x17,
-            // Self::ClrErrors => 0x07,
-            // Self::SetTCXOMode => 0x97,
-            // Self::SetTxFallbackMode => 0x93,
-            // Self::SetDIO2AsRfSwitchCtrl => 0x9d,
-            // Self::SetStopRxTimerOnPreamble => 0x9F,
-            // Self::SetLoRaSymbTimeout => 0xA0,
-            _ => *self as u8,
-        }
+            Self::SetRegulatorMode => 0x96,
+            Self::SetSaveContext => 0xd5,
+            Self::SetAutoFs => 0x9e,
+            Self::SetLongPreamble => 0x9b,
+            Self::SetRangingRole => 0xa3,
+            Self::SetAdvancedRnaging => 0x9a,
+            // 6x only. Note that some of these values are different commands on 8x; eg 0x97 (SetDio3AsTcxoCtrl)
+            // is ClrIrqStatus, and 0x98 (CalibrateImage) is SetAutoTx.
+            Self::SetPAConfig
+            | Self::GetStatistics
+            | Self::ResetStats
+            | Self::Calibrate
+            | Self::CalibrateImage
+            | Self::GetDeviceErrors
+            | Self::ClrErrors
+            | Self::SetDio3AsTcxoCtrl
+            | Self::SetRxTxFallbackMode
+            | Self::SetDIO2AsRfSwitchCtrl
+            | Self::SetStopRxTimerOnPreamble
+            | Self::SetLoRaSymbTimeout => return Err(RadioError::Config),
+        })
     }
 }
 
@@ -232,9 +328,9 @@ pub enum Register8x {
     WhiteningInitialValue = 0x9c5,
     CrcPolynomialDefinitionMsb = 0x9c6,
     CrcPolynomialDefinitionLsb = 0x9c7,
-    // CrcPolynomialSeedByte2 = 0x9c7, // todo what? Duplicate
-    // CrcPolynomialSeedByte1 = 0x9c8,
-    // CrcPolynomialSeedByte0 = 0x9c9,
+    // CrcPolynomialSeedByte2 = 0x9c7, // Same address as `CrcPolynomialDefinitionLsb`; use that.
+    CrcPolynomialSeedByte1 = 0x9c8,
+    CrcPolynomialSeedByte0 = 0x9c9,
     // CrcMsbInitialValue = 0x9c8, // todo: More dupes.
     // CrcLsbInitialValue = 0x9c9,
     SynchAddressControl = 0x9cd,
@@ -243,14 +339,82 @@ pub enum Register8x {
     SyncAddress1Byte2 = 0x9d0,
     SyncAddress1Byte1 = 0x9d1,
     SyncAddress1Byte0 = 0x9d2,
-    SyncAddress2Byte4 = 0x9c3,
-    SyncAddress2Byte3 = 0x9c4,
+    SyncAddress2Byte4 = 0x9d3,
+    SyncAddress2Byte3 = 0x9d4,
     SyncAddress2Byte2 = 0x9d5,
     SyncAddress2Byte1 = 0x9d6,
     SyncAddress2Byte0 = 0x9d7,
-    SyncAddress3Byte4 = 0x9c8,
-    SyncAddress3Byte3 = 0x9c9,
+    SyncAddress3Byte4 = 0x9d8,
+    SyncAddress3Byte3 = 0x9d9,
     SyncAddress3Byte2 = 0x9da,
     SyncAddress3Byte1 = 0x9db,
     SyncAddress3Byte0 = 0x9dc,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opcodes_8x() {
+        // 8x DS, Table 11-5.
+        let table = [
+            (OpCode::GetStatus, 0xc0),
+            (OpCode::WriteRegister, 0x18),
+            (OpCode::ReadRegister, 0x19),
+            (OpCode::WriteBuffer, 0x1a),
+            (OpCode::ReadBuffer, 0x1b),
+            (OpCode::SetSleep, 0x84),
+            (OpCode::SetStandby, 0x80),
+            (OpCode::SetFS, 0xc1),
+            (OpCode::SetTx, 0x83),
+            (OpCode::SetRx, 0x82),
+            (OpCode::SetRxDutyCycle, 0x94),
+            (OpCode::SetLongPreamble, 0x9b),
+            (OpCode::SetCAD, 0xc5),
+            (OpCode::SetTxContinuousWave, 0xd1),
+            (OpCode::SetTxContinuousPremable, 0xd2),
+            (OpCode::SetAutoFs, 0x9e),
+            (OpCode::SetPacketType, 0x8a),
+            (OpCode::GetPacketType, 0x03),
+            (OpCode::SetRfFrequency, 0x86),
+            (OpCode::SetTxParams, 0x8e),
+            (OpCode::SetCADParams, 0x88),
+            (OpCode::SetBufferBaseAddress, 0x8f),
+            (OpCode::SetModulationParams, 0x8b),
+            (OpCode::SetPacketParams, 0x8c),
+            (OpCode::GetRxBufferStatus, 0x17),
+            (OpCode::GetPacketStatus, 0x1d),
+            (OpCode::GetRSSIInst, 0x1f),
+            (OpCode::SetDioIrqParams, 0x8d),
+            (OpCode::GetIrqStatus, 0x15),
+            (OpCode::ClearIrqStatus, 0x97),
+            (OpCode::SetRegulatorMode, 0x96),
+            (OpCode::SetSaveContext, 0xd5),
+            (OpCode::SetRangingRole, 0xa3),
+            (OpCode::SetAdvancedRnaging, 0x9a),
+        ];
+
+        for (op, val) in table {
+            assert_eq!(op.val_8x(), Ok(val), "6x opcode {:#x}", op as u8);
+        }
+
+        // 6x only.
+        for op in [
+            OpCode::SetPAConfig,
+            OpCode::GetStatistics,
+            OpCode::ResetStats,
+            OpCode::Calibrate,
+            OpCode::CalibrateImage,
+            OpCode::GetDeviceErrors,
+            OpCode::ClrErrors,
+            OpCode::SetDio3AsTcxoCtrl,
+            OpCode::SetRxTxFallbackMode,
+            OpCode::SetDIO2AsRfSwitchCtrl,
+            OpCode::SetStopRxTimerOnPreamble,
+            OpCode::SetLoRaSymbTimeout,
+        ] {
+            assert_eq!(op.val_8x(), Err(RadioError::Config));
+        }
+    }
+}
@@ -1,11 +1,13 @@
 //! SPI interface commands for the radio.
+//!
+//! The SPI bus is accessed through `embedded-hal`'s `SpiDevice`, which owns chip select. This lets
+//! several radios (eg a 6x and an 8x) share one SPI peripheral, each with its own CS pin, using
+//! a bus-sharing wrapper such as those in `embedded-hal-bus`.
 
 use defmt::println;
-use hal::{
-    delay_us,
-    dma::{ChannelCfg, DmaChannel, DmaPeriph},
-    pac::SPI1,
-    spi::Spi,
+use embedded_hal::{
+    delay::DelayNs,
+    spi::{Operation, SpiDevice},
 };
 
 use crate::{
@@ -14,43 +16,65 @@ use crate::{
     status,
 };
 
-pub type Spi_ = Spi<SPI1>;
-
 // Note: Should be 256.
 pub const RADIO_BUF_SIZE: usize = 256;
 
-const AHB_FREQ: u32 = 170_000_000; // todo: temp hard-coded
-const DMA_PERIPH: DmaPeriph = DmaPeriph::Dma1; // todo: temp hard-coded
+/// Maximum SPI clock speed, in Hz, from the DS SPI timing sections. Exceeding this causes intermittent
+/// data corruption, which is hard to diagnose. Check your SPI clock configuration against these; `SpiDevice`
+/// doesn't expose its frequency, so we can't check it in `Radio::new`.
+pub const MAX_SPI_HZ_6X: u32 = 16_000_000;
+pub const MAX_SPI_HZ_8X: u32 = 18_000_000;
 
 use crate::shared::{RadioPins, Register};
 
-pub struct Interface {
-    pub spi: Spi_,
+pub struct Interface<SPI, D> {
+    /// Chip select is handled by the `SpiDevice`.
+    pub spi: SPI,
+    /// Used for reset timing.
+    pub delay: D,
     pub pins: RadioPins,
-    pub tx_ch: DmaChannel,
-    pub rx_ch: DmaChannel,
     pub read_buf: [u8; RADIO_BUF_SIZE],
-    pub write_buf: [u8; RADIO_BUF_SIZE],
     pub rx_payload_len: u8,
     pub rx_payload_start: u8,
+    /// The buffer index following the last packet read by `Radio::drain_rx`. Set to the Rx base address
+    /// when Rx starts. If `None`, `drain_rx` reads only the last packet received.
+    pub rx_read_ptr: Option<u8>,
     /// Otherwise, 6x.
     pub r8x: bool,
+    /// The number of consecutive low readings of the busy pin `wait_on_busy` requires before proceeding.
+    /// Increase this on boards where the busy line glitches, so commands aren't sent while the radio is
+    /// still busy. Defaults to 1; no debouncing. Values of 0 behave as 1.
+    pub busy_debounce: u8,
 }
 
-impl Interface {
+impl<SPI: SpiDevice, D: DelayNs> Interface<SPI, D> {
     pub fn reset(&mut self) {
         // Should only need 100us.
         self.pins.reset.set_low();
-        delay_us(500, AHB_FREQ);
+        self.delay.delay_us(500);
         self.pins.reset.set_high();
     }
 
+    /// Read the busy pin without blocking; eg for cooperative schedulers that yield while the radio is
+    /// busy, instead of spinning in `wait_on_busy`. If true, the radio is not ready for commands.
+    pub fn is_busy(&self) -> bool {
+        self.pins.busy.is_high()
+    }
+
     /// Wait for the radio to be ready to accept commands, using the busy pin. If the busy pin is high,
     /// the radio is not ready for commands.
+    /// The pin must read low `busy_debounce` times in a row.
     pub fn wait_on_busy(&mut self) -> Result<(), RadioError> {
         let mut i = 0;
+        let mut low_count = 0;
+
+        while low_count < self.busy_debounce.max(1) {
+            if self.is_busy() {
+                low_count = 0;
+            } else {
+                low_count += 1;
+            }
 
-        while self.pins.busy.is_high() {
             i += 1;
             if i >= MAX_ITERS {
                 println!("Exceeded max iters on wait on busy.");
@@ -64,43 +88,30 @@ impl Interface {
     pub fn write_op_word(&mut self, code: OpCode, word: u8) -> Result<(), RadioError> {
         self.wait_on_busy()?;
 
-        let c = if self.r8x { code.val_8x() } else { code as u8 };
-
-        self.pins.cs.set_low();
-
-        // if self.spi.write(&[c, word]).is_err() {
-        //     self.pins.cs.set_high();
-        //     return Err(RadioError::Spi);
-        // }
+        let c = if self.r8x { code.val_8x()? } else { code as u8 };
 
         let mut buf = [c, word];
-        if self.spi.transfer(&mut buf).is_err() {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        }
-        let status = status::status_from_byte(buf[0], self.r8x);
+        self.spi
+            .transfer_in_place(&mut buf)
+            .map_err(|_| RadioError::Spi)?;
+        let _status = status::status_from_byte(buf[0], self.r8x);
 
         // println!("STATUS OP WORD WRITE: {:x}, {:?}", code as u8, status);
 
-        self.pins.cs.set_high();
-
         Ok(())
     }
 
     /// Perform a read of an opcode, with 1 byte of data.
     pub fn read_op_word(&mut self, code: OpCode) -> Result<u8, RadioError> {
-        let c = if self.r8x { code.val_8x() } else { code as u8 };
+        let c = if self.r8x { code.val_8x()? } else { code as u8 };
 
         let mut buf = [c, 0, 0, 0, 0];
 
         self.wait_on_busy()?;
 
-        self.pins.cs.set_low();
-        if self.spi.transfer(&mut buf).is_err() {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        };
-        self.pins.cs.set_high();
+        self.spi
+            .transfer_in_place(&mut buf)
+            .map_err(|_| RadioError::Spi)?;
 
         // todo: Status is buf[1]. Use it? How do we interpret it?
         Ok(buf[2])
@@ -114,7 +125,7 @@ impl Interface {
         };
 
         let c = if self.r8x {
-            OpCode::WriteRegister.val_8x()
+            OpCode::WriteRegister.val_8x()?
         } else {
             OpCode::WriteRegister as u8
         };
@@ -123,18 +134,9 @@ impl Interface {
 
         self.wait_on_busy()?;
 
-        self.pins.cs.set_low();
-        if self
-            .spi
+        self.spi
             .write(&[c, addr_split.0, addr_split.1, word])
-            .is_err()
-        {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        };
-        self.pins.cs.set_high();
-
-        Ok(())
+            .map_err(|_| RadioError::Spi)
     }
 
     /// Common to 8-bit and 16-bit reads.
@@ -145,7 +147,7 @@ impl Interface {
         };
 
         let c = if self.r8x {
-            OpCode::ReadRegister.val_8x()
+            OpCode::ReadRegister.val_8x()?
         } else {
             OpCode::ReadRegister as u8
         };
@@ -156,20 +158,40 @@ impl Interface {
 
         self.wait_on_busy()?;
 
-        self.pins.cs.set_low();
-        if self
-            .spi
-            .transfer_type2(&[c, addr_split.0, addr_split.1, 0, 0, 0], &mut read_buf)
-            .is_err()
-        {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        };
-        self.pins.cs.set_high();
+        self.spi
+            .transfer(&mut read_buf, &[c, addr_split.0, addr_split.1, 0, 0, 0])
+            .map_err(|_| RadioError::Spi)?;
 
         Ok(read_buf)
     }
 
+    /// Read a contiguous range of registers, starting at `start`, into `out`, in a single transaction; the
+    /// radio auto-increments the address. Much faster than per-register reads; eg for diagnostic dumps.
+    /// `out` can be up to `RADIO_BUF_SIZE` bytes. 6x: DS, section 13.2.2. 8x: Table 11-31.
+    pub fn read_regs(&mut self, start: u16, out: &mut [u8]) -> Result<(), RadioError> {
+        if out.len() > RADIO_BUF_SIZE {
+            return Err(RadioError::PayloadSize(out.len()));
+        }
+
+        let c = if self.r8x {
+            OpCode::ReadRegister.val_8x()?
+        } else {
+            OpCode::ReadRegister as u8
+        };
+
+        let addr_split = shared::split_addr(start);
+
+        self.wait_on_busy()?;
+
+        // The fourth byte is a NOP, during which the radio returns status.
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[c, addr_split.0, addr_split.1, 0]),
+                Operation::Read(out),
+            ])
+            .map_err(|_| RadioError::Spi)
+    }
+
     /// Read a single 8-bit word from a register.
     pub fn read_reg_word(&mut self, reg: Register) -> Result<u8, RadioError> {
         Ok(self.read_reg_common(reg)?[4])
@@ -185,97 +207,53 @@ impl Interface {
     pub fn write(&mut self, write_buffer: &[u8]) -> Result<(), RadioError> {
         self.wait_on_busy()?;
 
-        self.pins.cs.set_low();
-        if self.spi.write(write_buffer).is_err() {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        }
-        self.pins.cs.set_high();
+        self.spi.write(write_buffer).map_err(|_| RadioError::Spi)
+    }
 
-        Ok(())
+    /// Request a read, filling the provided buffer.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), RadioError> {
+        self.wait_on_busy()?;
+
+        self.spi
+            .transfer_in_place(buffer)
+            .map_err(|_| RadioError::Spi)
     }
 
-    /// Write with a payload; uses DMA. Must clean up the transaction in an ISR. See note on offsets in
-    /// `read_with_payload`.
-    pub fn write_with_payload(&mut self, payload: &[u8], offset: u8) -> Result<(), RadioError> {
+    /// Write a payload to the radio's data buffer, starting at `offset`. The opcode and payload are sent
+    /// in a single transaction, without copying the payload into an intermediate buffer.
+    /// 6x: DS, section 13.2.3. 8x: Table 11-32.
+    pub fn write_buffer(&mut self, offset: u8, payload: &[u8]) -> Result<(), RadioError> {
         let c = if self.r8x {
-            OpCode::WriteBuffer.val_8x()
+            OpCode::WriteBuffer.val_8x()?
         } else {
             OpCode::WriteBuffer as u8
         };
-        self.write_buf[0] = c;
-        self.write_buf[1] = offset;
-
-        for (i, word) in payload.iter().enumerate() {
-            self.write_buf[i + 2] = *word;
-        }
 
         self.wait_on_busy()?;
 
-        println!("Writing DMA..."); // todo: Temp
-        self.pins.cs.set_low();
-        unsafe {
-            self.spi.write_dma(
-                &self.write_buf[..2 + payload.len()],
-                self.tx_ch,
-                Default::default(),
-                DMA_PERIPH,
-            );
-        }
-
-        Ok(())
+        self.spi
+            .transaction(&mut [Operation::Write(&[c, offset]), Operation::Write(payload)])
+            .map_err(|_| RadioError::Spi)
     }
 
-    /// Read a payload; uses DMA.
-    /// "Before any read or write operation it is hence necessary to initialize this offset to the corresponding beginning of the buffer.
-    /// Upon reading or writing to the data buffer the address pointer will then increment automatically."
-    pub fn read_with_payload(&mut self, payload_len: u8, offset: u8) -> Result<(), RadioError> {
+    /// Read `len` bytes from the radio's data buffer, starting at `offset`, into `read_buf`.
+    /// 6x: DS, section 13.2.4. 8x: Table 11-33.
+    pub fn read_buffer(&mut self, offset: u8, len: usize) -> Result<(), RadioError> {
         let c = if self.r8x {
-            OpCode::ReadBuffer.val_8x()
+            OpCode::ReadBuffer.val_8x()?
         } else {
             OpCode::ReadBuffer as u8
         };
 
-        self.write_buf[0] = c;
-        self.write_buf[1] = offset;
-        // "Note that the NOP must be sent after sending the offset."
-        self.write_buf[2] = 0;
-
-        // DS, Table 13-27: ReadBuffer SPI Transaction: Payload starts at byte 3.
-        let buf_end = payload_len as usize + 3;
-        // let buf_end = 4; // todo  t
-
         self.wait_on_busy()?;
 
-        println!("Reading DMA..."); // todo: temp
-        self.pins.cs.set_low();
-        unsafe {
-            self.spi.transfer_dma(
-                &self.write_buf[0..buf_end],
-                &mut self.read_buf[0..buf_end],
-                self.tx_ch,
-                self.rx_ch,
-                ChannelCfg::default(),
-                ChannelCfg::default(),
-                DMA_PERIPH,
-            );
-        }
-
-        Ok(())
-    }
-
-    /// Request a read, filling the provided buffer.
-    pub fn read(&mut self, buffer: &mut [u8]) -> Result<(), RadioError> {
-        self.wait_on_busy()?;
-
-        self.pins.cs.set_low();
-        if self.spi.transfer(buffer).is_err() {
-            self.pins.cs.set_high();
-            return Err(RadioError::Spi);
-        }
-        self.pins.cs.set_high();
-
-        Ok(())
+        // The third byte is a NOP, during which the radio returns status.
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[c, offset, 0]),
+                Operation::Read(&mut self.read_buf[..len]),
+            ])
+            .map_err(|_| RadioError::Spi)
     }
 
     /// // DS, Table 13-27: ReadBuffer SPI Transaction: Payload starts at byte 3, using the radio's API.
@@ -294,19 +272,4 @@ impl Interface {
 
         &mut self.read_buf[..self.rx_payload_len as usize]
     }
-
-    //
-    // /// Request a read, filling the provided buffer.
-    // pub fn read(&mut self, write_buffer: &[u8], read_buffer: &mut [u8]) -> Result<(), RadioError> {
-    //     self.wait_on_busy()?;
-    //
-    //     self.pins.cs.set_low();
-    //     if self.spi.transfer_type2(write_buffer, read_buffer).is_err() {
-    //         self.pins.cs.set_high();
-    //         return Err(RadioError::SPI);
-    //     }
-    //     self.pins.cs.set_high();
-    //
-    //     Ok(())
-    // }
 }
@@ -1,30 +1,44 @@
 //! Contains code related to assessing status of the radio and operations.
 
 use defmt::println;
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
 
 use crate::{
-    shared::{OpCode, RadioError, RadioError::UnexpectedStatus},
-    CommandStatus, OperatingModeRead, Radio, RadioConfig, RxBufferStatus, RxPacketStatusLora,
-    RxStatistics6x,
+    params::{LoraHeaderType, ModulationParams8x, PacketParams},
+    shared::{OpCode, RadioError, RadioError::UnexpectedStatus, Register::Reg8x, Register8x},
+    CommandStatus, Irq, OperatingModeRead, PacketType6x, PacketType8x, Radio, RadioConfig,
+    RxBufferStatus, RxPacketStatusLora, RxReport, RxStatistics6x,
 };
 
-impl Radio {
-    /// 6x only. DS, section 13.5.5
+/// 6x: DS, Table 13-85. OpError bit 6.
+const PLL_LOCK_ERR_6X: u16 = 1 << 6;
+
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
+    /// 6x only. DS, section 13.5.5. The 8x has no GetStatistics command, so this returns
+    /// `RadioError::Config` there.
     /// todo: Impl reset as well.
     pub fn get_statistics(&mut self) -> Result<RxStatistics6x, RadioError> {
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::GetStatistics as u8,
-            RadioConfig::R8x(_) => panic!("GetStatistics unavailabe on sx128x"),
+            RadioConfig::R8x(_) => return Err(RadioError::Config),
         };
 
         let mut buf = [op_code, 0, 0, 0, 0, 0, 0, 0];
         self.interface.read(&mut buf)?;
 
+        // Table 13-82: The third counter's meaning depends on packet type.
+        let third = u16::from_be_bytes([buf[6], buf[7]]);
+        let (num_length_error, num_header_error) = match &self.config {
+            RadioConfig::R6x(c) if c.packet_type == PacketType6x::Lora => (0, third),
+            _ => (third, 0),
+        };
+
         Ok(RxStatistics6x {
             status: buf[1],
             num_received: u16::from_be_bytes([buf[2], buf[3]]),
             num_crc_error: u16::from_be_bytes([buf[4], buf[5]]),
-            num_length_error: u16::from_be_bytes([buf[6], buf[7]]),
+            num_length_error,
+            num_header_error,
         })
     }
 
@@ -34,7 +48,7 @@ impl Radio {
     pub fn get_packet_status(&mut self) -> Result<RxPacketStatusLora, RadioError> {
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::GetPacketStatus as u8,
-            RadioConfig::R8x(_) => OpCode::GetPacketStatus.val_8x(),
+            RadioConfig::R8x(_) => OpCode::GetPacketStatus.val_8x()?,
         };
 
         let mut buf = [op_code, 0, 0, 0, 0];
@@ -51,31 +65,123 @@ impl Radio {
         })
     }
 
+    /// LoRa only. Gather the payload length, RSSI, SNR, CRC status, and (8x) frequency error of the last
+    /// received packet into one struct. Run this after RxDone, and before `cleanup_rx`, since that clears
+    /// the CRC error IRQ. This doesn't read the payload.
+    pub fn receive_report(&mut self) -> Result<RxReport, RadioError> {
+        match &self.config {
+            RadioConfig::R6x(c) if c.packet_type == PacketType6x::Lora => (),
+            RadioConfig::R8x(c) if c.packet_type == PacketType8x::Lora => (),
+            _ => return Err(RadioError::Config),
+        };
+        let crc_err = Irq::CrcErr.mask(self.interface.r8x);
+
+        let buf_status = self.get_rx_buffer_status()?;
+        let packet_status = self.get_packet_status()?;
+        let irq_status = self.read_irq_word()?;
+
+        let freq_error_hz = if self.interface.r8x {
+            Some(self.get_fei()?)
+        } else {
+            None
+        };
+
+        Ok(RxReport {
+            payload_len: buf_status.payload_len,
+            // Actual signal power is –RssiPkt/2.
+            rssi_dbm: -(packet_status.rssi as f32) / 2.,
+            snr_db: packet_status.snr_db(self.interface.r8x),
+            freq_error_hz,
+            crc_ok: irq_status & crc_err == 0,
+        })
+    }
+
+    /// 8x LoRa only. The frequency error of the last received packet, in Hz. DS, section 14.4.3, step 8:
+    /// The FEI is a 20-bit two's complement value. We use the scaling from Semtech's reference driver:
+    /// Hz = FEI * 1.55 / (1600 / BW in kHz). (The 6x doesn't report it for LoRa)
+    pub fn get_fei(&mut self) -> Result<i32, RadioError> {
+        let bw = match &self.config {
+            RadioConfig::R8x(c) if c.packet_type == PacketType8x::Lora => {
+                match &c.modulation_params {
+                    ModulationParams8x::Lora(m) => m.mod_bandwidth.hz(),
+                    _ => return Err(RadioError::ParamMismatch),
+                }
+            }
+            _ => return Err(RadioError::Config),
+        };
+
+        let b2 = self.interface.read_reg_word(Reg8x(Register8x::FeiByte2))?;
+        let b1 = self.interface.read_reg_word(Reg8x(Register8x::FeiByte1))?;
+        let b0 = self.interface.read_reg_word(Reg8x(Register8x::FeiByte0))?;
+
+        // Sign-extend from 20 bits.
+        let raw = i32::from_be_bytes([0, b2 & 0x0f, b1, b0]) << 12 >> 12;
+        Ok((raw as f32 * 1.55 * bw as f32 / 1_600_000.) as i32)
+    }
+
+    /// Read the raw packet status bytes, following the status byte. Use this to decode fields
+    /// `get_packet_status` doesn't cover; eg RSSI and error flags for GFSK, FLRC and BLE on 8x.
+    /// 6x: DS, section 13.5.3; only the first 3 bytes are used. 8x: DS, section 11.8.2.
+    pub fn get_packet_status_raw(&mut self) -> Result<[u8; 5], RadioError> {
+        let op_code = match self.config {
+            RadioConfig::R6x(_) => OpCode::GetPacketStatus as u8,
+            RadioConfig::R8x(_) => OpCode::GetPacketStatus.val_8x()?,
+        };
+
+        let mut buf = [op_code, 0, 0, 0, 0, 0, 0];
+        self.interface.read(&mut buf)?;
+
+        Ok([buf[2], buf[3], buf[4], buf[5], buf[6]])
+    }
+
     /// DS, section 13.5.4. todo: When would we use this over packet status?
+    /// The value is only meaningful while receiving; returns `RadioError::Config` if the radio isn't in
+    /// Rx mode. Use `enter_rssi_scan` to hold the radio in Rx for repeated reads, eg for squelch.
     pub fn get_rssi_inst(&mut self) -> Result<i8, RadioError> {
+        let (op_mode, _) = self.get_status()?;
+        if op_mode != OperatingModeRead::Rx {
+            return Err(RadioError::Config);
+        }
+
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::GetRSSIInst as u8,
-            RadioConfig::R8x(_) => OpCode::GetRSSIInst.val_8x(),
+            RadioConfig::R8x(_) => OpCode::GetRSSIInst.val_8x()?,
         };
 
         let mut buf = [op_code, 0, 0];
         self.interface.read(&mut buf)?;
 
-        Ok(-(buf[2] as i8) / 2)
+        Ok(rssi_inst_dbm(buf[2]))
     }
 
     /// 6x: 13.5.1
     /// 8x: 11.3. (Similar, but at different indices.
     pub fn get_status(&mut self) -> Result<(OperatingModeRead, CommandStatus), RadioError> {
+        let byte = self.get_status_byte()?;
+        self.decode_status(byte)
+    }
+
+    /// Read the raw status byte. See `get_status`.
+    pub(crate) fn get_status_byte(&mut self) -> Result<u8, RadioError> {
         let mut buf = [OpCode::GetStatus as u8, 0]; // This is OK; same OpCode on 6x and 8x.
         self.interface.read(&mut buf)?;
 
-        let (byte, is_8x) = match self.config {
-            RadioConfig::R6x(_) => (buf[1], false),
-            RadioConfig::R8x(_) => (buf[0], true),
-        };
+        Ok(match self.config {
+            RadioConfig::R6x(_) => buf[1],
+            RadioConfig::R8x(_) => buf[0],
+        })
+    }
 
-        status_from_byte(byte, is_8x)
+    /// Decode a raw status byte, passing it to `status_callback` if it's invalid.
+    pub(crate) fn decode_status(
+        &self,
+        byte: u8,
+    ) -> Result<(OperatingModeRead, CommandStatus), RadioError> {
+        status_from_byte(byte, self.interface.r8x).inspect_err(|_| {
+            if let Some(cb) = self.status_callback {
+                cb(byte);
+            }
+        })
     }
 
     /// 6x DS, section 13.5.2. This loads information related to the received payload; it may be useful
@@ -84,18 +190,90 @@ impl Radio {
     pub fn get_rx_buffer_status(&mut self) -> Result<RxBufferStatus, RadioError> {
         let op_code = match self.config {
             RadioConfig::R6x(_) => OpCode::GetRxBufferStatus as u8,
-            RadioConfig::R8x(_) => OpCode::GetRxBufferStatus.val_8x(),
+            RadioConfig::R8x(_) => OpCode::GetRxBufferStatus.val_8x()?,
         };
         let mut buf = [op_code, 0, 0, 0];
         self.interface.read(&mut buf)?;
 
+        // 8x: In LoRa implicit header mode, the payload length isn't reported; use the configured one.
+        // DS, section 11.8.1.
+        let payload_len = match &self.config {
+            RadioConfig::R8x(c) => match &c.packet_params {
+                PacketParams::Lora(p) if p.header_type == LoraHeaderType::FixedLength => {
+                    p.payload_len
+                }
+                _ => buf[2],
+            },
+            _ => buf[2],
+        };
+
         Ok(RxBufferStatus {
             status: buf[1],
-            payload_len: buf[2],
+            payload_len,
             rx_start_buf_pointer: buf[3],
         })
     }
 
+    /// Read the 16-bit IRQ status word. 6x: DS, section 13.3.3. 8x: Table 11-72.
+    pub(crate) fn read_irq_word(&mut self) -> Result<u16, RadioError> {
+        let op_code = match self.config {
+            RadioConfig::R6x(_) => OpCode::GetIrqStatus as u8,
+            RadioConfig::R8x(_) => OpCode::GetIrqStatus.val_8x()?,
+        };
+
+        let mut buf = [op_code, 0, 0, 0];
+        self.interface.read(&mut buf)?;
+
+        Ok(u16::from_be_bytes([buf[2], buf[3]]))
+    }
+
+    /// Read the IRQ status word, operating mode, and command status in a single SPI transaction; eg
+    /// for use in an ISR, in place of separate `get_irq_status` and `get_status` calls. This uses
+    /// the status byte the radio returns while the GetIrqStatus command is clocked in.
+    /// 6x: DS, section 13.3.3. 8x: Table 11-72.
+    pub fn irq_snapshot(&mut self) -> Result<(u16, OperatingModeRead, CommandStatus), RadioError> {
+        let (op_code, is_8x) = match self.config {
+            RadioConfig::R6x(_) => (OpCode::GetIrqStatus as u8, false),
+            RadioConfig::R8x(_) => (OpCode::GetIrqStatus.val_8x()?, true),
+        };
+
+        let mut buf = [op_code, 0, 0, 0];
+        self.interface.read(&mut buf)?;
+
+        // Status is at the same index as with `get_status`.
+        let status_byte = if is_8x { buf[0] } else { buf[1] };
+        let (op_mode, cmd_status) = self.decode_status(status_byte)?;
+
+        Ok((u16::from_be_bytes([buf[2], buf[3]]), op_mode, cmd_status))
+    }
+
+    /// Check that the radio entered FS, Tx, or Rx mode; this is run by `set_op_mode`.
+    ///
+    /// 6x: If `check_pll_lock` is set, uses the PLL lock bit in device errors, then clears it, returning
+    /// `RadioError::PllLock` on failure; eg from a bad crystal or TCXO, or an out-of-range frequency. DS,
+    /// Table 13-85. Otherwise, this does nothing.
+    ///
+    /// 8x: There are no device errors, so we can't identify a PLL lock failure specifically. Returns
+    /// `RadioError::Status` if the command status is "failure to execute" (possibly a PLL lock failure).
+    /// DS, Table 11-5.
+    pub fn ensure_locked(&mut self) -> Result<(), RadioError> {
+        match self.config {
+            RadioConfig::R6x(ref c) => {
+                if c.check_pll_lock && self.get_device_errors()? & PLL_LOCK_ERR_6X != 0 {
+                    self.interface.write(&[OpCode::ClrErrors as u8, 0, 0])?;
+                    return Err(RadioError::PllLock);
+                }
+            }
+            RadioConfig::R8x(_) => {
+                let (op_mode, cmd_status) = self.get_status()?;
+                if cmd_status == CommandStatus::FailureToExecuteCommand {
+                    return Err(RadioError::Status((op_mode, cmd_status)));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// 6x only.
     pub fn get_device_errors(&mut self) -> Result<u16, RadioError> {
         let mut buf = [OpCode::GetDeviceErrors as u8, 0, 0, 0];
@@ -106,15 +284,28 @@ impl Radio {
     }
 }
 
+/// Convert a raw instantaneous RSSI byte to dBm: Signal power in dBm = -RssiInst/2. Halve before
+/// negating, since raw values above 127 (below about -64dBm) don't fit in an `i8`. DS, section 13.5.4.
+pub(crate) fn rssi_inst_dbm(raw: u8) -> i8 {
+    -((raw / 2) as i8)
+}
+
 /// Helper function to get status from a byte.
+///
+/// 6x: DS, Table 13-76. Bits 6:4 are the chip mode, and bits 3:1 the command status.
+/// 8x: DS, Table 11-5. Bits 7:5 are the circuit mode, and bits 4:2 the command status. The status byte
+/// is clocked out during the first byte of every transaction.
+///
+/// The mode and command status codes are the same on both, except that command status 1 is
+/// "successfully processed" on 8x, and reserved on 6x.
 pub(crate) fn status_from_byte(
     byte: u8,
     r8x: bool,
 ) -> Result<(OperatingModeRead, CommandStatus), RadioError> {
-    let (om, c_s) = if !r8x {
-        ((byte >> 4) & 0b111, (byte >> 1) & 0b111)
-    } else {
+    let (om, c_s) = if r8x {
         ((byte >> 5) & 0b111, (byte >> 2) & 0b111)
+    } else {
+        ((byte >> 4) & 0b111, (byte >> 1) & 0b111)
     };
 
     let operating_mode = match om {
@@ -133,22 +324,69 @@ pub(crate) fn status_from_byte(
     };
 
     let command_status = match c_s {
-        1 => {
-            if !r8x {
-                println!("1 returned for command status. Investigate (\"RFU\" in DS; the future is now.)");
-                CommandStatus::FailureToExecuteCommand // bogus
-                                                       // todo: This should be removed in favof of unexpected status.
-            } else {
-                CommandStatus::CommandProcessSuccess8x
-            }
-        }
+        1 if r8x => CommandStatus::CommandProcessSuccess8x,
         2 => CommandStatus::DataAvailable,
         3 => CommandStatus::CommandTimeout,
         4 => CommandStatus::CommandProcessingError,
         5 => CommandStatus::FailureToExecuteCommand,
         6 => CommandStatus::CommandTxDone,
+        // Includes 1 on 6x, which is reserved.
         _ => return Err(UnexpectedStatus(c_s)),
     };
 
     Ok((operating_mode, command_status))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rssi_inst() {
+        assert_eq!(rssi_inst_dbm(0), 0);
+        assert_eq!(rssi_inst_dbm(0x7f), -63);
+        assert_eq!(rssi_inst_dbm(0x80), -64);
+        // A typical noise floor.
+        assert_eq!(rssi_inst_dbm(220), -110);
+        assert_eq!(rssi_inst_dbm(0xff), -127);
+    }
+
+    #[test]
+    fn status_bytes() {
+        // 6x: STDBY_RC, Tx done.
+        assert_eq!(
+            status_from_byte(0x2c, false),
+            Ok((OperatingModeRead::StbyRc, CommandStatus::CommandTxDone))
+        );
+        // 6x: Rx, data available.
+        assert_eq!(
+            status_from_byte(0x54, false),
+            Ok((OperatingModeRead::Rx, CommandStatus::DataAvailable))
+        );
+        // 6x: Command status 1 is reserved.
+        assert_eq!(status_from_byte(0x22, false), Err(UnexpectedStatus(1)));
+
+        // 8x: STDBY_RC, command processed successfully.
+        assert_eq!(
+            status_from_byte(0x44, true),
+            Ok((
+                OperatingModeRead::StbyRc,
+                CommandStatus::CommandProcessSuccess8x
+            ))
+        );
+        // 8x: FS, Rx timeout.
+        assert_eq!(
+            status_from_byte(0x8c, true),
+            Ok((OperatingModeRead::Fs, CommandStatus::CommandTimeout))
+        );
+        // 8x: Tx, Tx done.
+        assert_eq!(
+            status_from_byte(0xd8, true),
+            Ok((OperatingModeRead::Tx, CommandStatus::CommandTxDone))
+        );
+
+        // Mode 0 is unused on both.
+        assert_eq!(status_from_byte(0x0c, false), Err(UnexpectedStatus(0)));
+        assert_eq!(status_from_byte(0x18, true), Err(UnexpectedStatus(0)));
+    }
+}
@@ -0,0 +1,132 @@
+//! A software FIFO of pending transmissions, for bursty senders. Payloads are queued with
+//! `Radio::enqueue_tx`, and sent one at a time by calling `Radio::service_tx` from the TxDone ISR.
+
+use embedded_hal::{delay::DelayNs, spi::SpiDevice};
+
+use crate::{shared::RadioError, spi_interface::RADIO_BUF_SIZE, Radio};
+
+/// A ring buffer of up to `N` pending payloads, and their frequencies. Each slot reserves a full radio
+/// buffer, so keep `N` small.
+pub struct TxQueue<const N: usize> {
+    payloads: [[u8; RADIO_BUF_SIZE]; N],
+    lens: [u8; N],
+    freqs: [u32; N],
+    /// Index of the oldest entry.
+    head: usize,
+    count: usize,
+    /// A transmission is in progress; the next entry is sent on TxDone.
+    in_flight: bool,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            payloads: [[0; RADIO_BUF_SIZE]; N],
+            lens: [0; N],
+            freqs: [0; N],
+            head: 0,
+            count: 0,
+            in_flight: false,
+        }
+    }
+
+    /// The number of payloads waiting; excludes one in flight.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// A transmission started by the queue hasn't been serviced yet.
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight
+    }
+
+    /// Discard all pending payloads. Doesn't affect a transmission in progress.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.count = 0;
+    }
+
+    fn push(&mut self, payload: &[u8], rf_freq: u32) -> Result<(), RadioError> {
+        // The payload length is a single byte in packet params.
+        if payload.len() >= RADIO_BUF_SIZE {
+            return Err(RadioError::PayloadSize(payload.len()));
+        }
+        if self.count == N {
+            return Err(RadioError::QueueFull);
+        }
+
+        let i = (self.head + self.count) % N;
+        self.payloads[i][..payload.len()].copy_from_slice(payload);
+        self.lens[i] = payload.len() as u8;
+        self.freqs[i] = rf_freq;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    /// Remove the oldest entry, returning its slot index.
+    fn pop(&mut self) -> Option<usize> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let i = self.head;
+        self.head = (self.head + 1) % N;
+        self.count -= 1;
+
+        Some(i)
+    }
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SPI: SpiDevice, D: DelayNs> Radio<SPI, D> {
+    /// Queue a payload for transmission. If the queue has nothing in flight, it's sent immediately;
+    /// otherwise, it's sent by `service_tx` once the transmissions ahead of it complete. Returns
+    /// `RadioError::QueueFull` if there's no room. Don't mix this with direct calls to `send_payload` while
+    /// the queue is in flight.
+    pub fn enqueue_tx<const N: usize>(
+        &mut self,
+        queue: &mut TxQueue<N>,
+        payload: &[u8],
+        rf_freq: u32,
+    ) -> Result<(), RadioError> {
+        queue.push(payload, rf_freq)?;
+
+        if !queue.in_flight {
+            self.send_next(queue)?;
+        }
+
+        Ok(())
+    }
+
+    /// Call this from the TxDone ISR, in place of `cleanup_tx`, while using a `TxQueue`. Cleans up the
+    /// completed transmission, then starts the next queued one, if any.
+    pub fn service_tx<const N: usize>(&mut self, queue: &mut TxQueue<N>) -> Result<(), RadioError> {
+        queue.in_flight = false;
+        self.cleanup_tx()?;
+
+        self.send_next(queue)
+    }
+
+    /// Pop and send the next queued payload, if any.
+    fn send_next<const N: usize>(&mut self, queue: &mut TxQueue<N>) -> Result<(), RadioError> {
+        let Some(i) = queue.pop() else {
+            return Ok(());
+        };
+
+        let len = queue.lens[i] as usize;
+        self.send_payload(&queue.payloads[i][..len], queue.freqs[i])?;
+        queue.in_flight = true;
+
+        Ok(())
+    }
+}